@@ -0,0 +1,182 @@
+// Account Deletion Module
+// src/encrypted-notes-backend/src/account.rs
+//
+// Deleting an account takes two calls: a request opens a short confirmation
+// window and only a confirmation inside it runs the cascade. The cascade
+// deletes every note the caller owns except those minted as NFTs, hands
+// workspace notes to an admin, deletes the caller's groups and folders,
+// strips the caller from everything shared with them, declines transfers
+// offered to them, and finally drops the search index and profile so the
+// username can be taken again. The receipt lists what was removed.
+//
+// The cascade runs in the confirming message, so its cost grows with the
+// account's notes, groups, folders and shares and is capped by the
+// instruction limit of a single update call. An account past that limit
+// makes the confirmation trap, which rolls the whole cascade back and leaves
+// the account intact; the owner has to empty the trash or delete notes
+// before confirming again.
+
+use std::collections::BTreeSet;
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::update;
+
+use crate::archive::clear_account_import;
+use crate::folders::{delete_folders_of, file_note, revoke_folder_grants};
+use crate::groups::{delete_groups_of, leave_groups};
+use crate::helpers::assert_not_anonymous;
+use crate::inbox::forget_user;
+use crate::index::owned_note_ids;
+use crate::note::revoke_user_grants;
+use crate::search::delete_search_index_of;
+use crate::storage::{ACCOUNT_DELETIONS, NFTS, NOTES, TRASHED_NOTES};
+use crate::transfer::decline_incoming_transfers;
+use crate::trash::erase_note;
+use crate::types::{AccountDeletionReceipt, NoteId};
+use crate::user::delete_profile;
+use crate::workspaces::{leave_workspaces, sole_admin_workspaces};
+
+/// How long a deletion request can be confirmed (15 minutes)
+const ACCOUNT_DELETION_WINDOW_NANOS: u64 = 15 * 60 * 1_000_000_000;
+
+/// Ask to delete the caller's account
+/// Returns the deadline for `confirm_account_deletion`; asking again restarts the window
+#[update]
+pub fn request_account_deletion() -> u64 {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    assert_no_sole_admin(caller);
+
+    let deadline = ic_cdk::api::time() + ACCOUNT_DELETION_WINDOW_NANOS;
+    ACCOUNT_DELETIONS.with_borrow_mut(|requests| requests.insert(caller, deadline));
+    deadline
+}
+
+/// Withdraw the caller's pending deletion request, if any
+#[update]
+pub fn cancel_account_deletion() {
+    let caller = msg_caller();
+    ACCOUNT_DELETIONS.with_borrow_mut(|requests| requests.remove(&caller));
+}
+
+/// Delete the caller's account and everything it owns
+/// Requires a deletion request made within the last 15 minutes
+#[update]
+pub fn confirm_account_deletion() -> AccountDeletionReceipt {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let deadline = ACCOUNT_DELETIONS
+        .with_borrow_mut(|requests| requests.remove(&caller))
+        .unwrap_or_else(|| ic_cdk::trap("No account deletion was requested"));
+    let now = ic_cdk::api::time();
+    if !within_window(deadline, now) {
+        ic_cdk::trap("The account deletion request has expired; request it again");
+    }
+    assert_no_sole_admin(caller);
+
+    let (left_workspaces, mut retained_notes) = leave_workspaces(caller);
+    let (deleted_notes, minted_notes) = delete_owned_notes(caller);
+    retained_notes.extend(minted_notes);
+
+    let deleted_groups = delete_groups_of(caller);
+    let left_groups = leave_groups(caller);
+
+    let revoked_folder_shares = revoke_folder_grants(caller);
+    let deleted_folders = delete_folders_of(caller);
+    let revoked_shares = revoke_user_grants(caller);
+    let declined_transfers = decline_incoming_transfers(caller);
+    forget_user(caller);
+
+    let search_index_deleted = delete_search_index_of(caller);
+    let profile = delete_profile(caller);
+    clear_account_import(caller);
+
+    AccountDeletionReceipt {
+        principal: caller,
+        username: profile.as_ref().map(|profile| profile.username.clone()),
+        deleted_at: now,
+        deleted_notes,
+        retained_notes,
+        revoked_shares,
+        declined_transfers,
+        deleted_groups,
+        left_groups,
+        left_workspaces,
+        deleted_folders,
+        revoked_folder_shares,
+        search_index_deleted,
+        profile_deleted: profile.is_some(),
+    }
+}
+
+/// Erase the notes `owner` owns, live or trashed, except those minted as NFTs
+/// Minted notes are taken out of their folder and kept. Returns the deleted
+/// and the kept notes.
+fn delete_owned_notes(owner: Principal) -> (Vec<NoteId>, Vec<NoteId>) {
+    let mut deleted: Vec<NoteId> = TRASHED_NOTES.with_borrow(|trash| {
+        trash
+            .keys_range((owner, 0)..=(owner, NoteId::MAX))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    });
+    for note_id in &deleted {
+        erase_note(owner, *note_id);
+    }
+
+    let owned: BTreeSet<NoteId> = owned_note_ids(owner).into_iter().collect();
+    let minted: BTreeSet<NoteId> = NFTS.with_borrow(|store| {
+        store
+            .iter()
+            .map(|(_, nft)| nft.note_id)
+            .filter(|note_id| owned.contains(note_id))
+            .collect()
+    });
+
+    let mut retained = Vec::new();
+    for note_id in owned {
+        let Some(note) = NOTES.with_borrow(|store| store.get(&note_id)) else {
+            continue;
+        };
+        if minted.contains(&note_id) {
+            if note.folder_id.is_some() {
+                file_note(note_id, None, owner);
+            }
+            retained.push(note_id);
+        } else {
+            erase_note(owner, note_id);
+            deleted.push(note_id);
+        }
+    }
+    deleted.sort_unstable();
+    (deleted, retained)
+}
+
+/// Trap if leaving would strand a workspace without an admin
+fn assert_no_sole_admin(user: Principal) {
+    if let Some(workspace_id) = sole_admin_workspaces(user).first() {
+        ic_cdk::trap(format!(
+            "Appoint another admin of workspace {} before deleting your account",
+            workspace_id
+        ));
+    }
+}
+
+fn within_window(deadline: u64, now: u64) -> bool {
+    now <= deadline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deletion_window_closes_after_deadline() {
+        let deadline = 1_000 + ACCOUNT_DELETION_WINDOW_NANOS;
+
+        assert!(within_window(deadline, 1_000));
+        assert!(within_window(deadline, deadline));
+        assert!(!within_window(deadline, deadline + 1));
+    }
+}
@@ -0,0 +1,517 @@
+// Account Archive Module
+// src/encrypted-notes-backend/src/archive.rs
+//
+// Bulk export and import of everything a principal owns, for migrations
+// between canisters and compliance backups. An export is a sequence of
+// pages, each a self-contained candid-encoded `AccountArchive` whose items
+// stream the profile, the search index, every owned note (including trashed
+// ones) followed by its revisions, chunks, attachments and pending
+// operations, and finally the caller's NFTs.
+//
+// Importing restores the pages, in order, into the caller's account under
+// new note IDs and returns the old-to-new mapping. Ciphertext is restored
+// as-is: note keys are derived from the note ID, so clients must re-encrypt
+// imported content with the keys of the new IDs. Shares, groups, workspaces
+// and folders are canister-local and are not restored, and NFTs are not
+// re-minted.
+
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::attachments::{attachment_ids, MAX_ATTACHMENTS_PER_NOTE, MAX_ATTACHMENT_SIZE};
+use crate::changes::record_note_change;
+use crate::helpers::{assert_not_anonymous, get_max_note_size, get_next_id};
+use crate::index::{index_note, owned_note_ids};
+use crate::metadata::MAX_METADATA_SIZE;
+use crate::storage::{
+    ATTACHMENTS, IMPORT_NOTE_IDS, IMPORT_SESSIONS, NFTS, NOTES, NOTE_CHUNKS, NOTE_METADATA,
+    NOTE_OPS, NOTE_REVISIONS, SEARCH_INDICES, TRASHED_NOTES, USER_PROFILES,
+};
+use crate::trash::mark_trashed;
+use crate::types::{
+    AccountArchive, AccountExportPage, ArchiveItem, ArchivedNote, Attachment, ChangeKind,
+    ImportSession, Note, NoteId, NoteIdMapping, NoteOp, NoteRevision, PageRequest, SearchIndex,
+    UserProfile,
+};
+use crate::user::find_user_by_username;
+
+/// Current archive format; imports accept this version and older
+const ARCHIVE_VERSION: u32 = 1;
+/// Encoded item bytes per export page, leaving headroom below the 2 MiB reply limit
+const EXPORT_PAGE_BYTES: usize = 1536 * 1024;
+
+/// Position of an item in the export stream: (section, note or NFT ID, part, sub-ID)
+/// Items of a note are grouped behind its record so imports see the record first
+type ArchiveKey = (u8, u128, u8, u128);
+
+const SECTION_PROFILE: u8 = 0;
+const SECTION_SEARCH_INDEX: u8 = 1;
+const SECTION_NOTES: u8 = 2;
+const SECTION_NFTS: u8 = 3;
+
+const PART_RECORD: u8 = 0;
+const PART_REVISION: u8 = 1;
+const PART_CHUNK: u8 = 2;
+const PART_ATTACHMENT: u8 = 3;
+const PART_OP: u8 = 4;
+
+/// Export the next page of the caller's data, starting after `request.cursor`
+/// Pages are sized by bytes rather than `request.limit`; keep calling with
+/// `next_cursor` until it is `None`
+#[query]
+pub fn export_account_page(request: PageRequest) -> AccountExportPage {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let after = request.cursor.as_deref().map(|cursor| {
+        parse_key(cursor)
+            .unwrap_or_else(|| ic_cdk::trap(format!("Invalid export cursor: {}", cursor)))
+    });
+
+    let mut items = Vec::new();
+    let mut size = 0;
+    let mut last_key = None;
+    let mut has_more = false;
+
+    for key in export_keys(caller, after) {
+        let Some(item) = load_item(caller, key) else {
+            continue;
+        };
+        let item_size = Encode!(&item).map_or(0, |bytes| bytes.len());
+        if !items.is_empty() && size + item_size > EXPORT_PAGE_BYTES {
+            has_more = true;
+            break;
+        }
+        size += item_size;
+        last_key = Some(key);
+        items.push(item);
+    }
+
+    let archive = AccountArchive {
+        version: ARCHIVE_VERSION,
+        exported_by: caller,
+        exported_at: ic_cdk::api::time(),
+        items,
+    };
+
+    AccountExportPage {
+        archive: Encode!(&archive)
+            .unwrap_or_else(|e| ic_cdk::trap(format!("Failed to encode archive: {}", e))),
+        next_cursor: if has_more {
+            last_key.map(format_key)
+        } else {
+            None
+        },
+    }
+}
+
+/// Start importing an archive into the caller's account
+/// Only one import per principal can be in progress
+#[update]
+pub fn begin_account_import() {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    if IMPORT_SESSIONS.with_borrow(|sessions| sessions.contains_key(&caller)) {
+        ic_cdk::trap("An import is already in progress; finish it first");
+    }
+
+    let session = ImportSession {
+        owner: caller,
+        started_at: ic_cdk::api::time(),
+        notes_imported: 0,
+    };
+    IMPORT_SESSIONS.with_borrow_mut(|sessions| sessions.insert(caller, session));
+}
+
+/// Import one exported page into the caller's account
+/// Pages must be imported in export order; a profile or search index is only
+/// restored if the caller has none yet and the username is still free
+#[update]
+pub fn import_account_page(archive: Vec<u8>) {
+    let caller = msg_caller();
+    let mut session = IMPORT_SESSIONS
+        .with_borrow(|sessions| sessions.get(&caller))
+        .unwrap_or_else(|| ic_cdk::trap("No import in progress"));
+
+    let archive = decode_archive(&archive).unwrap_or_else(|e| ic_cdk::trap(e));
+    for item in archive.items {
+        match item {
+            ArchiveItem::Profile(profile) => import_profile(caller, profile),
+            ArchiveItem::SearchIndex(index) => import_search_index(caller, index),
+            ArchiveItem::Note(archived) => {
+                import_note(caller, *archived);
+                session.notes_imported += 1;
+            }
+            ArchiveItem::Revision(revision) => {
+                let note_id = imported_note_id(caller, revision.note_id);
+                assert_fits(revision.encrypted.len(), get_max_note_size(), "Revision");
+                NOTE_REVISIONS.with_borrow_mut(|store| {
+                    store.insert(
+                        (note_id, revision.revision_id),
+                        NoteRevision {
+                            note_id,
+                            ..revision
+                        },
+                    )
+                });
+            }
+            ArchiveItem::Chunk {
+                note_id,
+                index,
+                data,
+            } => {
+                let note_id = imported_note_id(caller, note_id);
+                let chunk_count =
+                    NOTES.with_borrow(|store| store.get(&note_id).map_or(0, |n| n.chunk_count));
+                if index >= chunk_count {
+                    ic_cdk::trap(format!("Unexpected chunk {} of note {}", index, note_id));
+                }
+                assert_fits(data.len(), get_max_note_size(), "Chunk");
+                NOTE_CHUNKS.with_borrow_mut(|chunks| chunks.insert((note_id, index), data));
+            }
+            ArchiveItem::Attachment(attachment) => {
+                let note_id = imported_note_id(caller, attachment.note_id);
+                assert_fits(attachment.data.len(), MAX_ATTACHMENT_SIZE, "Attachment");
+                if attachment_ids(note_id).len() >= MAX_ATTACHMENTS_PER_NOTE {
+                    ic_cdk::trap(format!("Too many attachments on note {}", note_id));
+                }
+                // Attachment IDs come from the global counter and are reassigned
+                let attachment_id = get_next_id();
+                ATTACHMENTS.with_borrow_mut(|store| {
+                    store.insert(
+                        (note_id, attachment_id),
+                        Attachment {
+                            id: attachment_id,
+                            note_id,
+                            size: attachment.data.len() as u64,
+                            ..attachment
+                        },
+                    )
+                });
+            }
+            ArchiveItem::Op(op) => import_op(caller, op),
+            ArchiveItem::Nft(_) => {}
+        }
+    }
+
+    IMPORT_SESSIONS.with_borrow_mut(|sessions| sessions.insert(caller, session));
+}
+
+/// Finish the caller's import and return the note ID mapping, ordered by old ID
+#[update]
+pub fn finish_account_import() -> Vec<NoteIdMapping> {
+    let caller = msg_caller();
+
+    if IMPORT_SESSIONS
+        .with_borrow_mut(|sessions| sessions.remove(&caller))
+        .is_none()
+    {
+        ic_cdk::trap("No import in progress");
+    }
+
+    let mapping: Vec<NoteIdMapping> = IMPORT_NOTE_IDS.with_borrow(|ids| {
+        ids.range((caller, 0)..=(caller, NoteId::MAX))
+            .map(|((_, old_id), new_id)| NoteIdMapping { old_id, new_id })
+            .collect()
+    });
+    IMPORT_NOTE_IDS.with_borrow_mut(|ids| {
+        for entry in &mapping {
+            ids.remove(&(caller, entry.old_id));
+        }
+    });
+    mapping
+}
+
+/// Abandon any import `user` has in progress, keeping the notes already imported
+pub fn clear_account_import(user: Principal) {
+    IMPORT_SESSIONS.with_borrow_mut(|sessions| sessions.remove(&user));
+
+    let old_ids: Vec<NoteId> = IMPORT_NOTE_IDS.with_borrow(|ids| {
+        ids.keys_range((user, 0)..=(user, NoteId::MAX))
+            .map(|(_, old_id)| old_id)
+            .collect()
+    });
+    IMPORT_NOTE_IDS.with_borrow_mut(|ids| {
+        for old_id in old_ids {
+            ids.remove(&(user, old_id));
+        }
+    });
+}
+
+/// Keys of every item the caller can export, in stream order, after `after`
+fn export_keys(caller: Principal, after: Option<ArchiveKey>) -> Vec<ArchiveKey> {
+    let mut keys = vec![(SECTION_PROFILE, 0, 0, 0), (SECTION_SEARCH_INDEX, 0, 0, 0)];
+
+    let mut note_ids = owned_note_ids(caller);
+    note_ids.extend(TRASHED_NOTES.with_borrow(|trash| {
+        trash
+            .keys_range((caller, 0)..=(caller, NoteId::MAX))
+            .map(|(_, note_id)| note_id)
+            .collect::<Vec<_>>()
+    }));
+    note_ids.sort_unstable();
+    note_ids.dedup();
+    // Notes before the cursor are skipped without listing their parts
+    if let Some((section, note_id, _, _)) = after {
+        note_ids.retain(|id| (SECTION_NOTES, *id) >= (section, note_id));
+    }
+
+    for note_id in note_ids {
+        let Some(note) = NOTES.with_borrow(|store| store.get(&note_id)) else {
+            continue;
+        };
+        keys.push((SECTION_NOTES, note_id, PART_RECORD, 0));
+        NOTE_REVISIONS.with_borrow(|store| {
+            keys.extend(store.keys_range((note_id, 0)..=(note_id, u64::MAX)).map(
+                |(_, revision_id)| (SECTION_NOTES, note_id, PART_REVISION, revision_id as u128),
+            ))
+        });
+        keys.extend(
+            (0..note.chunk_count).map(|index| (SECTION_NOTES, note_id, PART_CHUNK, index as u128)),
+        );
+        keys.extend(
+            attachment_ids(note_id)
+                .into_iter()
+                .map(|attachment_id| (SECTION_NOTES, note_id, PART_ATTACHMENT, attachment_id)),
+        );
+        NOTE_OPS.with_borrow(|log| {
+            keys.extend(
+                log.keys_range((note_id, 0)..=(note_id, u128::MAX))
+                    .map(|(_, seq)| (SECTION_NOTES, note_id, PART_OP, seq)),
+            )
+        });
+    }
+
+    NFTS.with_borrow(|nfts| {
+        keys.extend(
+            nfts.iter()
+                .filter(|(_, nft)| nft.owner == caller)
+                .map(|(nft_id, _)| (SECTION_NFTS, nft_id, 0, 0)),
+        )
+    });
+
+    keys.retain(|key| after.is_none_or(|after| *key > after));
+    keys
+}
+
+fn load_item(caller: Principal, key: ArchiveKey) -> Option<ArchiveItem> {
+    match key {
+        (SECTION_PROFILE, ..) => {
+            USER_PROFILES.with_borrow(|profiles| profiles.get(&caller).map(ArchiveItem::Profile))
+        }
+        (SECTION_SEARCH_INDEX, ..) => {
+            SEARCH_INDICES.with_borrow(|indices| indices.get(&caller).map(ArchiveItem::SearchIndex))
+        }
+        (SECTION_NOTES, note_id, PART_RECORD, _) => {
+            NOTES.with_borrow(|store| store.get(&note_id)).map(|note| {
+                ArchiveItem::Note(Box::new(ArchivedNote {
+                    note,
+                    metadata: NOTE_METADATA.with_borrow(|sidecars| sidecars.get(&note_id)),
+                }))
+            })
+        }
+        (SECTION_NOTES, note_id, PART_REVISION, revision_id) => NOTE_REVISIONS
+            .with_borrow(|store| store.get(&(note_id, revision_id as u64)))
+            .map(ArchiveItem::Revision),
+        (SECTION_NOTES, note_id, PART_CHUNK, index) => NOTE_CHUNKS
+            .with_borrow(|chunks| chunks.get(&(note_id, index as u32)))
+            .map(|data| ArchiveItem::Chunk {
+                note_id,
+                index: index as u32,
+                data,
+            }),
+        (SECTION_NOTES, note_id, PART_ATTACHMENT, attachment_id) => ATTACHMENTS
+            .with_borrow(|store| store.get(&(note_id, attachment_id)))
+            .map(ArchiveItem::Attachment),
+        (SECTION_NOTES, note_id, PART_OP, seq) => NOTE_OPS
+            .with_borrow(|log| log.get(&(note_id, seq)))
+            .map(ArchiveItem::Op),
+        (SECTION_NFTS, nft_id, ..) => NFTS
+            .with_borrow(|nfts| nfts.get(&nft_id))
+            .map(ArchiveItem::Nft),
+        _ => None,
+    }
+}
+
+fn format_key((section, id, part, sub): ArchiveKey) -> String {
+    format!("{}.{}.{}.{}", section, id, part, sub)
+}
+
+fn parse_key(cursor: &str) -> Option<ArchiveKey> {
+    let mut parts = cursor.split('.');
+    let key = (
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+    );
+    parts.next().is_none().then_some(key)
+}
+
+/// Decode an exported page, rejecting formats newer than this canister understands
+fn decode_archive(bytes: &[u8]) -> Result<AccountArchive, String> {
+    let archive = Decode!(bytes, AccountArchive).map_err(|e| format!("Invalid archive: {}", e))?;
+    if archive.version == 0 || archive.version > ARCHIVE_VERSION {
+        return Err(format!("Unsupported archive version {}", archive.version));
+    }
+    Ok(archive)
+}
+
+fn import_profile(caller: Principal, profile: UserProfile) {
+    let username = profile.username.trim().to_string();
+    let taken = find_user_by_username(&username).is_some();
+    if taken
+        || username.is_empty()
+        || USER_PROFILES.with_borrow(|profiles| profiles.contains_key(&caller))
+    {
+        return;
+    }
+
+    USER_PROFILES.with_borrow_mut(|profiles| {
+        profiles.insert(
+            caller,
+            UserProfile {
+                id: caller,
+                username,
+                email: profile.email,
+            },
+        )
+    });
+}
+
+fn import_search_index(caller: Principal, index: SearchIndex) {
+    SEARCH_INDICES.with_borrow_mut(|indices| {
+        if !indices.contains_key(&caller) {
+            indices.insert(
+                caller,
+                SearchIndex {
+                    owner: caller,
+                    ..index
+                },
+            );
+        }
+    });
+}
+
+fn import_note(caller: Principal, archived: ArchivedNote) {
+    let old = archived.note;
+    if IMPORT_NOTE_IDS.with_borrow(|ids| ids.contains_key(&(caller, old.id))) {
+        ic_cdk::trap(format!("Note {} was already imported", old.id));
+    }
+    assert_fits(old.encrypted.len(), get_max_note_size(), "Note");
+
+    let note_id = get_next_id();
+    let note = Note {
+        id: note_id,
+        owner: caller,
+        encrypted: old.encrypted,
+        shared_read: vec![],
+        shared_edit: vec![],
+        version: old.version,
+        created_at: old.created_at,
+        updated_at: old.updated_at,
+        last_editor: old.last_editor,
+        trashed_at: old.trashed_at,
+        chunk_count: old.chunk_count,
+        share_expirations: vec![],
+        // Epochs are kept so the epoch of every imported ciphertext still matches
+        key_epoch: old.key_epoch,
+        content_key_epoch: old.content_key_epoch,
+        shared_read_groups: vec![],
+        shared_edit_groups: vec![],
+        workspace_id: None,
+        shared_comment: vec![],
+        shared_comment_groups: vec![],
+        folder_id: None,
+        op_log_clock: old.op_log_clock,
+    };
+
+    match note.trashed_at {
+        Some(trashed_at) => {
+            mark_trashed(caller, note_id, trashed_at);
+        }
+        None => index_note(&note),
+    }
+    record_note_change(None, &note, ChangeKind::Created);
+    NOTES.with_borrow_mut(|store| store.insert(note_id, note));
+
+    if let Some(metadata) = archived.metadata {
+        assert_fits(metadata.encrypted.len(), MAX_METADATA_SIZE, "Metadata");
+        NOTE_METADATA.with_borrow_mut(|sidecars| sidecars.insert(note_id, metadata));
+    }
+    IMPORT_NOTE_IDS.with_borrow_mut(|ids| ids.insert((caller, old.id), note_id));
+}
+
+fn import_op(caller: Principal, op: NoteOp) {
+    let note_id = imported_note_id(caller, op.note_id);
+    let in_op_log_mode = NOTES.with_borrow(|store| {
+        store
+            .get(&note_id)
+            .is_some_and(|note| note.op_log_clock.is_some())
+    });
+    if !in_op_log_mode {
+        ic_cdk::trap(format!("Note {} is not in op-log mode", note_id));
+    }
+
+    let seq = get_next_id();
+    NOTE_OPS.with_borrow_mut(|log| log.insert((note_id, seq), NoteOp { note_id, seq, ..op }));
+}
+
+/// New ID of a note imported earlier in the caller's current import
+fn imported_note_id(caller: Principal, old_id: NoteId) -> NoteId {
+    IMPORT_NOTE_IDS
+        .with_borrow(|ids| ids.get(&(caller, old_id)))
+        .unwrap_or_else(|| {
+            ic_cdk::trap(format!(
+                "Archive references note {} before its record",
+                old_id
+            ))
+        })
+}
+
+fn assert_fits(size: usize, max_size: usize, what: &str) {
+    if size > max_size {
+        ic_cdk::trap(format!(
+            "{} too large: {} bytes exceeds limit of {} bytes",
+            what, size, max_size
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(version: u32) -> Vec<u8> {
+        Encode!(&AccountArchive {
+            version,
+            exported_by: Principal::anonymous(),
+            exported_at: 0,
+            items: vec![],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cursor_round_trip_preserves_stream_order() {
+        let record = (SECTION_NOTES, 7, PART_RECORD, 0);
+        let attachment = (SECTION_NOTES, 7, PART_ATTACHMENT, 99);
+        let next_note = (SECTION_NOTES, 8, PART_RECORD, 0);
+
+        assert_eq!(parse_key(&format_key(attachment)), Some(attachment));
+        assert!(record < attachment && attachment < next_note);
+        assert_eq!(parse_key("2.7.0"), None);
+        assert_eq!(parse_key("2.7.0.0.1"), None);
+        assert_eq!(parse_key("2.x.0.0"), None);
+    }
+
+    #[test]
+    fn test_decode_archive_checks_version() {
+        assert!(decode_archive(&archive(ARCHIVE_VERSION)).is_ok());
+        assert!(decode_archive(&archive(ARCHIVE_VERSION + 1)).is_err());
+        assert!(decode_archive(&archive(0)).is_err());
+        assert!(decode_archive(b"not candid").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+// Note Attachments Module
+// src/encrypted-notes-backend/src/attachments.rs
+//
+// Attachments are client-encrypted with the same vetKD-derived symmetric key
+// as their parent note (`encrypted_symmetric_key_for_note`), so the canister
+// only stores opaque bytes. Each attachment records the key epoch it was
+// encrypted under, so it stays readable after the note's key is rotated.
+// Access follows the parent note: `can_read` to list and download, `can_edit`
+// to add and remove. On a transfer the previous owner re-encrypts them for the
+// recipient (see transfer.rs); an NFT sale drops them, as they stay encrypted
+// under the seller's key.
+
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::helpers::{assert_not_anonymous, get_next_id};
+use crate::note::{assert_can_edit, assert_can_read, assert_latest_key_epoch};
+use crate::storage::{ATTACHMENTS, NOTES};
+use crate::types::{Attachment, AttachmentId, AttachmentInfo, NoteId};
+
+/// Maximum size of a single encrypted attachment (1 MiB, below the ingress message limit)
+pub const MAX_ATTACHMENT_SIZE: usize = 1024 * 1024;
+/// Maximum number of attachments on one note
+pub const MAX_ATTACHMENTS_PER_NOTE: usize = 32;
+const MAX_MIME_TYPE_LENGTH: usize = 255;
+
+/// Attach client-encrypted bytes to a note
+/// Requires edit permissions on the note and data encrypted under its latest key epoch;
+/// returns the new attachment ID
+#[update]
+pub fn add_attachment(
+    note_id: NoteId,
+    mime_type: String,
+    data: Vec<u8>,
+    key_epoch: Option<u64>,
+) -> AttachmentId {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    assert_can_edit(note_id, &caller);
+    if let Some(note) = NOTES.with_borrow(|store| store.get(&note_id)) {
+        assert_latest_key_epoch(&note, key_epoch);
+    }
+
+    if mime_type.trim().is_empty() || mime_type.len() > MAX_MIME_TYPE_LENGTH {
+        ic_cdk::trap("Invalid attachment mime type");
+    }
+    if data.len() > MAX_ATTACHMENT_SIZE {
+        ic_cdk::trap(format!(
+            "Attachment too large: {} bytes exceeds limit of {} bytes",
+            data.len(),
+            MAX_ATTACHMENT_SIZE
+        ));
+    }
+    if attachment_ids(note_id).len() >= MAX_ATTACHMENTS_PER_NOTE {
+        ic_cdk::trap(format!(
+            "Note already has the maximum of {} attachments",
+            MAX_ATTACHMENTS_PER_NOTE
+        ));
+    }
+
+    let attachment_id = get_next_id();
+    let attachment = Attachment {
+        id: attachment_id,
+        note_id,
+        mime_type: mime_type.trim().to_string(),
+        size: data.len() as u64,
+        data,
+        uploaded_by: caller,
+        created_at: ic_cdk::api::time(),
+        key_epoch,
+    };
+
+    ATTACHMENTS.with_borrow_mut(|store| store.insert((note_id, attachment_id), attachment));
+
+    attachment_id
+}
+
+/// List the attachments of a note without their contents
+/// Requires read permissions on the note
+#[query]
+pub fn list_attachments(note_id: NoteId) -> Vec<AttachmentInfo> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    assert_can_read(note_id, &caller);
+
+    ATTACHMENTS.with_borrow(|store| {
+        store
+            .range(attachment_range(note_id))
+            .map(|(_, attachment)| AttachmentInfo {
+                id: attachment.id,
+                note_id: attachment.note_id,
+                mime_type: attachment.mime_type,
+                size: attachment.size,
+                uploaded_by: attachment.uploaded_by,
+                created_at: attachment.created_at,
+                key_epoch: attachment.key_epoch,
+            })
+            .collect()
+    })
+}
+
+/// Download an attachment
+/// Returns the attachment if caller has read permissions on the note
+#[query]
+pub fn get_attachment(note_id: NoteId, attachment_id: AttachmentId) -> Option<Attachment> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let readable = NOTES.with_borrow(|store| {
+        store
+            .get(&note_id)
+            .map(|note| note.can_read(&caller))
+            .unwrap_or(false)
+    });
+    if !readable {
+        return None;
+    }
+
+    ATTACHMENTS.with_borrow(|store| store.get(&(note_id, attachment_id)))
+}
+
+/// Remove an attachment from a note
+/// Requires edit permissions on the note
+#[update]
+pub fn delete_attachment(note_id: NoteId, attachment_id: AttachmentId) {
+    let caller = msg_caller();
+    assert_can_edit(note_id, &caller);
+
+    let removed = ATTACHMENTS.with_borrow_mut(|store| store.remove(&(note_id, attachment_id)));
+    if removed.is_none() {
+        ic_cdk::trap("Attachment not found");
+    }
+}
+
+/// Remove every attachment of a note
+pub fn clear_attachments(note_id: NoteId) {
+    let ids = attachment_ids(note_id);
+    ATTACHMENTS.with_borrow_mut(|store| {
+        for attachment_id in ids {
+            store.remove(&(note_id, attachment_id));
+        }
+    });
+}
+
+fn attachment_range(note_id: NoteId) -> std::ops::RangeInclusive<(NoteId, AttachmentId)> {
+    (note_id, 0)..=(note_id, AttachmentId::MAX)
+}
+
+pub fn attachment_ids(note_id: NoteId) -> Vec<AttachmentId> {
+    ATTACHMENTS.with_borrow(|store| {
+        store
+            .keys_range(attachment_range(note_id))
+            .map(|(_, attachment_id)| attachment_id)
+            .collect()
+    })
+}
@@ -0,0 +1,112 @@
+// Note Audit Log Module
+// src/encrypted-notes-backend/src/audit.rs
+//
+// Every successful read, key request, update, share and unshare of a note
+// appends an entry to that note's log. Logs are bounded by a controller-set
+// cap: once a note exceeds it, its oldest entries are dropped. Only the note
+// owner can read the log.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+use std::ops::Bound;
+
+use crate::helpers::{assert_not_anonymous, now, paginate, parse_cursor};
+use crate::storage::{AUDIT_LOG, AUDIT_LOG_CAP, NOTES};
+use crate::types::{AuditAction, AuditEntry, NoteId, Page, PageRequest};
+
+/// Append an entry to a note's audit log, dropping entries beyond the cap
+pub fn record_access(note_id: NoteId, principal: Principal, action: AuditAction) {
+    let cap = get_audit_log_cap();
+
+    AUDIT_LOG.with_borrow_mut(|log| {
+        let seq = log
+            .keys_range(log_range(note_id))
+            .next_back()
+            .map_or(0, |(_, seq)| seq + 1);
+        log.insert(
+            (note_id, seq),
+            AuditEntry {
+                seq,
+                principal,
+                action,
+                timestamp: now(),
+            },
+        );
+
+        if seq >= cap {
+            let expired: Vec<(NoteId, u64)> = log
+                .keys_range((note_id, 0)..=(note_id, seq - cap))
+                .collect();
+            for key in expired {
+                log.remove(&key);
+            }
+        }
+    });
+}
+
+/// List one page of a note's audit log, oldest entry first
+/// Only the owner can read the log
+#[query]
+pub fn get_note_audit_log(note_id: NoteId, request: PageRequest) -> Page<AuditEntry> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    match NOTES.with_borrow(|store| store.get(&note_id)) {
+        Some(note) if note.is_managed_by(&caller) => {}
+        Some(_) => ic_cdk::trap("Only owner can read the audit log"),
+        None => ic_cdk::trap("Note not found"),
+    }
+
+    let start = match parse_cursor::<u64>(&request) {
+        Some(cursor) => Bound::Excluded((note_id, cursor)),
+        None => Bound::Included((note_id, 0)),
+    };
+
+    AUDIT_LOG.with_borrow(|log| {
+        let entries = log
+            .range((start, Bound::Included((note_id, u64::MAX))))
+            .map(|((_, seq), entry)| (seq, entry));
+        paginate(entries, &request)
+    })
+}
+
+/// Set how many audit entries are kept per note
+/// Only callable by controllers; takes effect on each note's next entry
+#[update]
+pub fn set_audit_log_cap(cap: u64) -> Result<(), String> {
+    let caller = msg_caller();
+
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can set the audit log cap".to_string());
+    }
+    if cap == 0 {
+        return Err("Audit log cap must be at least 1".to_string());
+    }
+
+    AUDIT_LOG_CAP.with_borrow_mut(|cell| {
+        cell.set(cap)
+            .map_err(|_| "Failed to update audit log cap".to_string())
+            .map(|_| ())
+    })
+}
+
+/// Get how many audit entries are kept per note
+#[query]
+pub fn get_audit_log_cap() -> u64 {
+    AUDIT_LOG_CAP.with_borrow(|cell| *cell.get())
+}
+
+/// Remove the whole audit log of a note
+pub fn clear_audit_log(note_id: NoteId) {
+    AUDIT_LOG.with_borrow_mut(|log| {
+        let keys: Vec<(NoteId, u64)> = log.keys_range(log_range(note_id)).collect();
+        for key in keys {
+            log.remove(&key);
+        }
+    });
+}
+
+fn log_range(note_id: NoteId) -> std::ops::RangeInclusive<(NoteId, u64)> {
+    (note_id, 0)..=(note_id, u64::MAX)
+}
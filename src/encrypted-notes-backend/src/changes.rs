@@ -0,0 +1,199 @@
+// Changefeed Module
+// src/encrypted-notes-backend/src/changes.rs
+//
+// Every note create, update, trash, restore, delete, access change and
+// ownership change is appended to CHANGE_LOG under a global, monotonically
+// increasing sequence. Offline-first clients poll `get_changes` with the
+// last cursor they saw and receive the current state of every note they can
+// read that changed since, plus tombstones for notes that were deleted or
+// that they lost access to. Visibility is evaluated at read time, so an entry
+// recorded before a grant still shows the note to its new reader.
+
+use std::collections::BTreeSet;
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::query;
+
+use crate::folders::folder_audience;
+use crate::helpers::{assert_not_anonymous, now, page_size};
+use crate::storage::{CHANGE_LOG, GROUPS, NOTES, WORKSPACES};
+use crate::types::{ChangeEntry, ChangeFeed, ChangeKind, Note, NoteChange, NoteId, PageRequest};
+
+/// Most log entries inspected by a single `get_changes` call
+const MAX_CHANGES_SCANNED: usize = 5000;
+
+/// Record a change to `after`, which was `before` until now
+/// Principals who could read `before` but cannot read `after` get a tombstone
+pub fn record_note_change(before: Option<&Note>, after: &Note, kind: ChangeKind) {
+    let revoked = before.map_or_else(Vec::new, |before| {
+        readers(before)
+            .into_iter()
+            .filter(|principal| !after.can_read(principal))
+            .collect()
+    });
+    append(after.id, kind, revoked);
+}
+
+/// Record that `note` was permanently deleted
+pub fn record_note_deleted(note: &Note) {
+    append(note.id, ChangeKind::Deleted, readers(note));
+}
+
+/// Record a change in who can read `note` that did not modify the note
+/// itself, e.g. a group or workspace membership change; those of
+/// `principals` who can no longer read the note get a tombstone
+pub fn record_access_change(note: &Note, principals: &[Principal]) {
+    let revoked = principals
+        .iter()
+        .filter(|principal| !note.can_read(principal))
+        .copied()
+        .collect();
+    append(note.id, ChangeKind::AccessChanged, revoked);
+}
+
+/// Get the changes visible to the caller after `request.cursor`, oldest first
+/// Each note appears at most once per batch, with its current state; keep
+/// calling with the returned cursor while `has_more` is set
+#[query]
+pub fn get_changes(request: PageRequest) -> ChangeFeed {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let after: u64 = request.cursor.as_ref().map_or(0, |cursor| {
+        cursor
+            .parse()
+            .unwrap_or_else(|_| ic_cdk::trap(format!("Invalid change cursor: {}", cursor)))
+    });
+    let limit = page_size(&request);
+
+    let mut items = Vec::new();
+    let mut seen: BTreeSet<NoteId> = BTreeSet::new();
+    let mut cursor = after;
+    let mut has_more = false;
+
+    CHANGE_LOG.with_borrow(|log| {
+        NOTES.with_borrow(|store| {
+            for (scanned, (seq, entry)) in log.range(after + 1..).enumerate() {
+                if items.len() == limit || scanned == MAX_CHANGES_SCANNED {
+                    has_more = true;
+                    break;
+                }
+                cursor = seq;
+                if seen.contains(&entry.note_id) {
+                    continue;
+                }
+
+                let note = store
+                    .get(&entry.note_id)
+                    .filter(|note| note.can_read(&caller));
+                if note.is_none() && !entry.revoked.contains(&caller) {
+                    continue;
+                }
+
+                seen.insert(entry.note_id);
+                items.push(NoteChange {
+                    seq,
+                    note_id: entry.note_id,
+                    kind: entry.kind,
+                    timestamp: entry.timestamp,
+                    note,
+                });
+            }
+        })
+    });
+
+    ChangeFeed {
+        items,
+        cursor,
+        has_more,
+    }
+}
+
+/// Latest change sequence, a starting cursor for clients that just did a full download
+#[query]
+pub fn get_change_cursor() -> u64 {
+    CHANGE_LOG.with_borrow(|log| log.last_key_value().map_or(0, |(seq, _)| seq))
+}
+
+fn append(note_id: NoteId, kind: ChangeKind, revoked: Vec<Principal>) {
+    CHANGE_LOG.with_borrow_mut(|log| {
+        let seq = log.last_key_value().map_or(1, |(seq, _)| seq + 1);
+        log.insert(
+            seq,
+            ChangeEntry {
+                seq,
+                note_id,
+                kind,
+                timestamp: now(),
+                revoked,
+            },
+        );
+    });
+}
+
+/// Everyone who may be able to read `note`: owner, grantees, and members of
+/// its groups, workspace and folders
+fn readers(note: &Note) -> Vec<Principal> {
+    let mut readers: BTreeSet<Principal> = BTreeSet::new();
+    readers.insert(note.owner);
+    readers.extend(note.grants().into_iter().map(|grant| grant.user));
+
+    GROUPS.with_borrow(|groups| {
+        for group_id in note
+            .shared_read_groups
+            .iter()
+            .chain(note.shared_edit_groups.iter())
+            .chain(note.shared_comment_groups.iter())
+        {
+            if let Some(group) = groups.get(group_id) {
+                readers.extend(group.members);
+            }
+        }
+    });
+    if let Some(workspace_id) = note.workspace_id {
+        if let Some(workspace) = WORKSPACES.with_borrow(|workspaces| workspaces.get(&workspace_id))
+        {
+            readers.extend(workspace.members.iter().map(|member| member.principal));
+        }
+    }
+    if let Some(folder_id) = note.folder_id {
+        readers.extend(folder_audience(folder_id));
+    }
+
+    readers.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note, principal};
+    use crate::types::Group;
+
+    #[test]
+    fn test_readers_include_grantees_and_group_members() {
+        let (alice, bob, carol) = (principal(1), principal(2), principal(3));
+        GROUPS.with_borrow_mut(|groups| {
+            groups.insert(
+                7,
+                Group {
+                    id: 7,
+                    owner: alice,
+                    name: "team".to_string(),
+                    members: vec![carol],
+                    created_at: 0,
+                    updated_at: 0,
+                },
+            )
+        });
+        let note = Note {
+            shared_read: vec![bob],
+            shared_read_groups: vec![7],
+            ..note(1, alice)
+        };
+
+        let mut expected = vec![alice, bob, carol];
+        expected.sort();
+        assert_eq!(readers(&note), expected);
+    }
+}
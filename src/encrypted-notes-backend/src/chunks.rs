@@ -0,0 +1,231 @@
+// Chunked Note Content Module
+// src/encrypted-notes-backend/src/chunks.rs
+//
+// Notes larger than MAX_NOTE_SIZE are uploaded as ordered encrypted chunks:
+// `begin_note_upload`, one `upload_note_chunk` per chunk, then
+// `commit_note_upload`, which moves the staged chunks into NOTE_CHUNKS in a
+// single message. A chunked note keeps `encrypted` empty and records its
+// `chunk_count`; clients page through the content with `get_note_chunk`.
+// Chunked content is only replaced by another upload, which drops the old
+// chunks without archiving them; inline edits of a chunked note are rejected.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::helpers::{assert_not_anonymous, get_max_note_size, get_next_id};
+use crate::leases::assert_not_leased;
+use crate::note::{assert_can_edit, assert_latest_key_epoch, insert_note, replace_with_chunks};
+use crate::oplog::assert_not_op_log;
+use crate::storage::{NOTES, NOTE_CHUNKS, UPLOAD_CHUNKS, UPLOAD_SESSIONS};
+use crate::types::{Note, NoteId, UploadId, UploadSession};
+
+/// Upper bound on the total size of a chunked note (64 MiB)
+const MAX_CHUNKED_NOTE_SIZE: u64 = 64 * 1024 * 1024;
+/// Sessions without activity for this long are garbage-collected (1 hour)
+const UPLOAD_SESSION_TTL_NANOS: u64 = 60 * 60 * 1_000_000_000;
+
+/// Start a chunked upload
+/// With `note_id` set, committing replaces that note's content (requires edit
+/// permissions); otherwise committing creates a new note owned by the caller
+/// `key_epoch` is the epoch the chunks are encrypted under and must be the
+/// note's latest epoch when the upload is committed
+#[update]
+pub fn begin_note_upload(note_id: Option<NoteId>, key_epoch: Option<u64>) -> UploadId {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    if let Some(note_id) = note_id {
+        assert_can_edit(note_id, &caller);
+    }
+
+    let upload_id = get_next_id();
+    let now = ic_cdk::api::time();
+    let session = UploadSession {
+        id: upload_id,
+        owner: caller,
+        note_id,
+        chunk_count: 0,
+        total_size: 0,
+        created_at: now,
+        updated_at: now,
+        key_epoch,
+    };
+
+    UPLOAD_SESSIONS.with_borrow_mut(|sessions| sessions.insert(upload_id, session));
+
+    upload_id
+}
+
+/// Append the next chunk to an upload session
+/// Chunks must arrive in order: `index` has to equal the number of chunks already uploaded
+#[update]
+pub fn upload_note_chunk(upload_id: UploadId, index: u32, chunk: String) {
+    let caller = msg_caller();
+    let mut session = owned_session(upload_id, &caller);
+
+    if index != session.chunk_count {
+        ic_cdk::trap(format!(
+            "Out of order chunk: expected index {}, got {}",
+            session.chunk_count, index
+        ));
+    }
+
+    let max_size = get_max_note_size();
+    if chunk.len() > max_size {
+        ic_cdk::trap(format!(
+            "Chunk too large: {} bytes exceeds limit of {} bytes",
+            chunk.len(),
+            max_size
+        ));
+    }
+
+    let total_size = session.total_size + chunk.len() as u64;
+    if total_size > MAX_CHUNKED_NOTE_SIZE {
+        ic_cdk::trap(format!(
+            "Note too large: {} bytes exceeds chunked limit of {} bytes",
+            total_size, MAX_CHUNKED_NOTE_SIZE
+        ));
+    }
+
+    UPLOAD_CHUNKS.with_borrow_mut(|chunks| chunks.insert((upload_id, index), chunk));
+
+    session.chunk_count += 1;
+    session.total_size = total_size;
+    session.updated_at = ic_cdk::api::time();
+    UPLOAD_SESSIONS.with_borrow_mut(|sessions| sessions.insert(upload_id, session));
+}
+
+/// Commit an upload session into a note
+/// Returns the ID of the created or updated note
+#[update]
+pub fn commit_note_upload(upload_id: UploadId) -> NoteId {
+    let caller = msg_caller();
+    let session = owned_session(upload_id, &caller);
+
+    if session.chunk_count == 0 {
+        ic_cdk::trap("Cannot commit an upload without chunks");
+    }
+
+    let note_id = match session.note_id {
+        Some(note_id) => {
+            NOTES.with_borrow_mut(|store| {
+                let mut note = store
+                    .get(&note_id)
+                    .unwrap_or_else(|| ic_cdk::trap("Note not found"));
+                if !note.can_edit(&caller) {
+                    ic_cdk::trap("Not authorized to update this note");
+                }
+                assert_not_op_log(&note);
+                assert_not_leased(&note, &caller);
+                assert_latest_key_epoch(&note, session.key_epoch);
+
+                replace_with_chunks(&mut note, session.chunk_count, caller);
+                store.insert(note_id, note);
+            });
+            note_id
+        }
+        None => {
+            if session.key_epoch.unwrap_or(0) != 0 {
+                ic_cdk::trap("New notes start at key epoch 0");
+            }
+            insert_note(caller, String::new(), session.chunk_count, None)
+        }
+    };
+
+    UPLOAD_CHUNKS.with_borrow_mut(|staged| {
+        NOTE_CHUNKS.with_borrow_mut(|chunks| {
+            for index in 0..session.chunk_count {
+                if let Some(chunk) = staged.remove(&(upload_id, index)) {
+                    chunks.insert((note_id, index), chunk);
+                }
+            }
+        });
+    });
+    UPLOAD_SESSIONS.with_borrow_mut(|sessions| sessions.remove(&upload_id));
+
+    note_id
+}
+
+/// Abandon an upload session and drop its staged chunks
+#[update]
+pub fn abort_note_upload(upload_id: UploadId) {
+    let caller = msg_caller();
+    let session = owned_session(upload_id, &caller);
+
+    discard_session(&session);
+}
+
+/// Get one chunk of a chunked note's content
+/// Returns the chunk if caller has read permissions; `Note.chunk_count` gives the number of chunks
+#[query]
+pub fn get_note_chunk(note_id: NoteId, index: u32) -> Option<String> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let readable = NOTES.with_borrow(|store| {
+        store
+            .get(&note_id)
+            .map(|note| note.can_read(&caller))
+            .unwrap_or(false)
+    });
+    if !readable {
+        return None;
+    }
+
+    NOTE_CHUNKS.with_borrow(|chunks| chunks.get(&(note_id, index)))
+}
+
+/// Trap if `note` is chunked, where content only changes through a new upload
+pub fn assert_not_chunked(note: &Note) {
+    if note.is_chunked() {
+        ic_cdk::trap("Note is chunked; replace its content with a new chunked upload");
+    }
+}
+
+/// Remove every stored chunk of a note
+pub fn clear_note_chunks(note_id: NoteId) {
+    NOTE_CHUNKS.with_borrow_mut(|chunks| {
+        let keys: Vec<(NoteId, u32)> = chunks
+            .keys_range((note_id, 0)..=(note_id, u32::MAX))
+            .collect();
+        for key in keys {
+            chunks.remove(&key);
+        }
+    });
+}
+
+/// Drop upload sessions that have been idle longer than the session TTL
+/// Runs periodically from the timer registered in timers.rs
+pub fn collect_abandoned_uploads() {
+    let cutoff = ic_cdk::api::time().saturating_sub(UPLOAD_SESSION_TTL_NANOS);
+
+    let abandoned: Vec<UploadSession> = UPLOAD_SESSIONS.with_borrow(|sessions| {
+        sessions
+            .iter()
+            .filter(|(_, session)| session.updated_at < cutoff)
+            .map(|(_, session)| session)
+            .collect()
+    });
+
+    for session in abandoned {
+        discard_session(&session);
+    }
+}
+
+fn owned_session(upload_id: UploadId, caller: &Principal) -> UploadSession {
+    match UPLOAD_SESSIONS.with_borrow(|sessions| sessions.get(&upload_id)) {
+        Some(session) if &session.owner == caller => session,
+        Some(_) => ic_cdk::trap("Not authorized to use this upload session"),
+        None => ic_cdk::trap("Upload session not found"),
+    }
+}
+
+fn discard_session(session: &UploadSession) {
+    UPLOAD_CHUNKS.with_borrow_mut(|staged| {
+        for index in 0..session.chunk_count {
+            staged.remove(&(session.id, index));
+        }
+    });
+    UPLOAD_SESSIONS.with_borrow_mut(|sessions| sessions.remove(&session.id));
+}
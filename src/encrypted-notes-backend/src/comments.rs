@@ -0,0 +1,181 @@
+// Note Comments Module
+// src/encrypted-notes-backend/src/comments.rs
+//
+// Threaded comments on a note. Bodies are client-encrypted under the note's
+// vetKD key and record the key epoch they were written with, like
+// attachments. Anyone who can read the note can list its comments; posting,
+// replying and resolving require `can_comment` (editors and commenters).
+// Only the author can edit or delete a comment.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+use std::ops::Bound;
+
+use crate::helpers::{assert_not_anonymous, get_next_id, paginate, parse_cursor};
+use crate::note::{assert_can_read, assert_latest_key_epoch};
+use crate::storage::{NOTES, NOTE_COMMENTS};
+use crate::types::{Comment, CommentId, Note, NoteId, Page, PageRequest};
+
+/// Maximum size of an encrypted comment body (16 KiB)
+const MAX_COMMENT_SIZE: usize = 16 * 1024;
+
+/// Post a comment on a note, or a reply when `parent_id` is set
+/// Requires comment permissions; `key_epoch` must be the note's latest epoch
+#[update]
+pub fn add_comment(
+    note_id: NoteId,
+    parent_id: Option<CommentId>,
+    encrypted_body: String,
+    key_epoch: u64,
+) -> CommentId {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    let note = commentable_note(note_id, &caller);
+    assert_latest_key_epoch(&note, Some(key_epoch));
+    validate_body(&encrypted_body);
+
+    if let Some(parent_id) = parent_id {
+        let parent_exists =
+            NOTE_COMMENTS.with_borrow(|comments| comments.contains_key(&(note_id, parent_id)));
+        if !parent_exists {
+            ic_cdk::trap("Parent comment not found");
+        }
+    }
+
+    let comment_id = get_next_id();
+    let now = ic_cdk::api::time();
+    let comment = Comment {
+        id: comment_id,
+        note_id,
+        parent_id,
+        author: caller,
+        encrypted_body,
+        key_epoch,
+        resolved: false,
+        resolved_by: None,
+        deleted: false,
+        created_at: now,
+        updated_at: now,
+    };
+
+    NOTE_COMMENTS.with_borrow_mut(|comments| comments.insert((note_id, comment_id), comment));
+
+    comment_id
+}
+
+/// Replace the body of a comment
+/// Only the author can edit, and only while they can still comment on the note
+#[update]
+pub fn edit_comment(
+    note_id: NoteId,
+    comment_id: CommentId,
+    encrypted_body: String,
+    key_epoch: u64,
+) {
+    let caller = msg_caller();
+    let note = commentable_note(note_id, &caller);
+    assert_latest_key_epoch(&note, Some(key_epoch));
+    validate_body(&encrypted_body);
+
+    let mut comment = authored_comment(note_id, comment_id, &caller);
+    comment.encrypted_body = encrypted_body;
+    comment.key_epoch = key_epoch;
+    comment.updated_at = ic_cdk::api::time();
+    NOTE_COMMENTS.with_borrow_mut(|comments| comments.insert((note_id, comment_id), comment));
+}
+
+/// Delete a comment
+/// Only the author can delete; replies stay attached to the emptied comment
+#[update]
+pub fn delete_comment(note_id: NoteId, comment_id: CommentId) {
+    let caller = msg_caller();
+    assert_can_read(note_id, &caller);
+
+    let mut comment = authored_comment(note_id, comment_id, &caller);
+    comment.encrypted_body = String::new();
+    comment.deleted = true;
+    comment.updated_at = ic_cdk::api::time();
+    NOTE_COMMENTS.with_borrow_mut(|comments| comments.insert((note_id, comment_id), comment));
+}
+
+/// Mark a comment thread as resolved or reopen it
+/// Requires comment permissions on the note
+#[update]
+pub fn set_comment_resolved(note_id: NoteId, comment_id: CommentId, resolved: bool) {
+    let caller = msg_caller();
+    commentable_note(note_id, &caller);
+
+    NOTE_COMMENTS.with_borrow_mut(|comments| {
+        let mut comment = comments
+            .get(&(note_id, comment_id))
+            .unwrap_or_else(|| ic_cdk::trap("Comment not found"));
+        comment.resolved = resolved;
+        comment.resolved_by = resolved.then_some(caller);
+        comment.updated_at = ic_cdk::api::time();
+        comments.insert((note_id, comment_id), comment);
+    });
+}
+
+/// List one page of a note's comments in posting order
+/// Requires read permissions; clients assemble threads from `parent_id`
+#[query]
+pub fn list_comments(note_id: NoteId, request: PageRequest) -> Page<Comment> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    assert_can_read(note_id, &caller);
+
+    let start = match parse_cursor::<CommentId>(&request) {
+        Some(cursor) => Bound::Excluded((note_id, cursor)),
+        None => Bound::Included((note_id, 0)),
+    };
+
+    NOTE_COMMENTS.with_borrow(|comments| {
+        let entries = comments
+            .range((start, Bound::Included((note_id, CommentId::MAX))))
+            .map(|((_, comment_id), comment)| (comment_id, comment));
+        paginate(entries, &request)
+    })
+}
+
+/// Remove every comment of a note
+pub fn clear_comments(note_id: NoteId) {
+    NOTE_COMMENTS.with_borrow_mut(|comments| {
+        let keys: Vec<(NoteId, CommentId)> = comments
+            .keys_range((note_id, 0)..=(note_id, CommentId::MAX))
+            .collect();
+        for key in keys {
+            comments.remove(&key);
+        }
+    });
+}
+
+fn commentable_note(note_id: NoteId, caller: &Principal) -> Note {
+    match NOTES.with_borrow(|store| store.get(&note_id)) {
+        Some(note) if note.can_comment(caller) => note,
+        Some(_) => ic_cdk::trap("Not authorized to comment on this note"),
+        None => ic_cdk::trap("Note not found"),
+    }
+}
+
+fn authored_comment(note_id: NoteId, comment_id: CommentId, caller: &Principal) -> Comment {
+    match NOTE_COMMENTS.with_borrow(|comments| comments.get(&(note_id, comment_id))) {
+        Some(comment) if comment.deleted => ic_cdk::trap("Comment has been deleted"),
+        Some(comment) if &comment.author == caller => comment,
+        Some(_) => ic_cdk::trap("Only the author can change this comment"),
+        None => ic_cdk::trap("Comment not found"),
+    }
+}
+
+fn validate_body(encrypted_body: &str) {
+    if encrypted_body.is_empty() {
+        ic_cdk::trap("Comment cannot be empty");
+    }
+    if encrypted_body.len() > MAX_COMMENT_SIZE {
+        ic_cdk::trap(format!(
+            "Comment too large: {} bytes exceeds limit of {} bytes",
+            encrypted_body.len(),
+            MAX_COMMENT_SIZE
+        ));
+    }
+}
@@ -0,0 +1,692 @@
+// Folders Module
+// src/encrypted-notes-backend/src/folders.rs
+//
+// Folders organize an owner's notes into a tree. Names are client-encrypted
+// under a per-folder vetKD key. Notes record the folder they are filed in and
+// FOLDER_NOTES indexes them, so listing a folder never scans NOTES. A grant
+// on a folder is resolved at check time through the note's folder and its
+// ancestors, which makes it cover notes and subfolders added later. Like
+// group shares, folder shares skip the share inbox but honour the blocklist.
+// Revoking a folder grant, or moving something out from under a shared
+// folder, rotates the key epoch of the affected folders and notes.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::changes::{record_access_change, record_note_change};
+use crate::helpers::{assert_not_anonymous, get_next_id};
+use crate::inbox::is_blocked;
+use crate::index::{folder_note_ids, index_note, unindex_note};
+use crate::note::{derive_encrypted_key, notes_page};
+use crate::storage::{
+    FOLDERS, FOLDER_CHILDREN, FOLDER_GRANTEES, FOLDER_NOTES, NOTES, OWNER_FOLDERS,
+};
+use crate::types::{
+    ChangeKind, Folder, FolderId, Note, NoteId, Page, PageRequest, SharePermission,
+};
+
+const MAX_FOLDER_NAME_SIZE: usize = 1024;
+/// Deepest allowed nesting, counting the root folder as depth 1
+const MAX_FOLDER_DEPTH: usize = 16;
+
+/// Strongest permission `principal` holds on `folder_id` through it or any ancestor
+pub fn folder_permission(folder_id: FolderId, principal: &Principal) -> Option<SharePermission> {
+    ancestors(folder_id)
+        .iter()
+        .filter_map(|folder| folder.permission_of(principal))
+        .max_by_key(|permission| permission_rank(*permission))
+}
+
+/// Everyone holding a grant on `folder_id` or any ancestor
+pub fn folder_audience(folder_id: FolderId) -> Vec<Principal> {
+    ancestors(folder_id)
+        .iter()
+        .flat_map(|folder| {
+            folder
+                .shared_read
+                .iter()
+                .chain(folder.shared_edit.iter())
+                .chain(folder.shared_comment.iter())
+                .copied()
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// `folder_id` followed by every folder nested beneath it
+pub fn subtree_folder_ids(folder_id: FolderId) -> Vec<FolderId> {
+    let mut ids = vec![folder_id];
+    let mut next = 0;
+    while next < ids.len() {
+        ids.extend(child_folder_ids(ids[next]));
+        next += 1;
+    }
+    ids
+}
+
+/// Whether `folder_id` exists and belongs to `owner`
+pub fn is_folder_of(folder_id: FolderId, owner: &Principal) -> bool {
+    FOLDERS
+        .with_borrow(|folders| folders.get(&folder_id))
+        .is_some_and(|folder| &folder.owner == owner)
+}
+
+/// Create a folder owned by the caller, at the top level or inside `parent_id`
+/// `encrypted_name` must be encrypted under the new folder's key at epoch 0
+/// Returns the new folder ID
+#[update]
+pub fn create_folder(encrypted_name: String, parent_id: Option<FolderId>) -> FolderId {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    validate_folder_name(&encrypted_name);
+
+    if let Some(parent_id) = parent_id {
+        owned_folder(parent_id, &caller);
+        if ancestors(parent_id).len() >= MAX_FOLDER_DEPTH {
+            ic_cdk::trap(format!(
+                "Folders can be nested at most {} levels deep",
+                MAX_FOLDER_DEPTH
+            ));
+        }
+    }
+
+    let folder_id = get_next_id();
+    let now = ic_cdk::api::time();
+    let folder = Folder {
+        id: folder_id,
+        owner: caller,
+        parent_id,
+        encrypted_name,
+        shared_read: vec![],
+        shared_edit: vec![],
+        shared_comment: vec![],
+        key_epoch: 0,
+        name_key_epoch: 0,
+        created_at: now,
+        updated_at: now,
+    };
+
+    OWNER_FOLDERS.with_borrow_mut(|index| index.insert((caller, folder_id), ()));
+    if let Some(parent_id) = parent_id {
+        FOLDER_CHILDREN.with_borrow_mut(|children| children.insert((parent_id, folder_id), ()));
+    }
+    FOLDERS.with_borrow_mut(|folders| folders.insert(folder_id, folder));
+
+    folder_id
+}
+
+/// Replace a folder's encrypted name
+/// Only the owner can rename; the name must be encrypted under the latest key epoch
+#[update]
+pub fn rename_folder(folder_id: FolderId, encrypted_name: String, key_epoch: u64) {
+    let caller = msg_caller();
+    let mut folder = owned_folder(folder_id, &caller);
+    validate_folder_name(&encrypted_name);
+
+    if key_epoch != folder.key_epoch {
+        ic_cdk::trap(format!(
+            "Folder name must be re-encrypted under key epoch {}",
+            folder.key_epoch
+        ));
+    }
+
+    folder.encrypted_name = encrypted_name;
+    folder.name_key_epoch = key_epoch;
+    folder.updated_at = ic_cdk::api::time();
+    FOLDERS.with_borrow_mut(|folders| folders.insert(folder_id, folder));
+}
+
+/// Move a folder, with everything inside it, to the top level or into `parent_id`
+/// Only the owner can move folders; a folder cannot be moved into itself
+#[update]
+pub fn move_folder(folder_id: FolderId, parent_id: Option<FolderId>) {
+    let caller = msg_caller();
+    let mut folder = owned_folder(folder_id, &caller);
+    if folder.parent_id == parent_id {
+        return;
+    }
+
+    if let Some(parent_id) = parent_id {
+        owned_folder(parent_id, &caller);
+        if subtree_folder_ids(folder_id).contains(&parent_id) {
+            ic_cdk::trap("Cannot move a folder into itself");
+        }
+        if ancestors(parent_id).len() + subtree_height(folder_id) > MAX_FOLDER_DEPTH {
+            ic_cdk::trap(format!(
+                "Folders can be nested at most {} levels deep",
+                MAX_FOLDER_DEPTH
+            ));
+        }
+    }
+
+    let old_audience = folder.parent_id.map_or_else(Vec::new, folder_audience);
+
+    FOLDER_CHILDREN.with_borrow_mut(|children| {
+        if let Some(old_parent) = folder.parent_id {
+            children.remove(&(old_parent, folder_id));
+        }
+        if let Some(parent_id) = parent_id {
+            children.insert((parent_id, folder_id), ());
+        }
+    });
+    folder.parent_id = parent_id;
+    folder.updated_at = ic_cdk::api::time();
+    FOLDERS.with_borrow_mut(|folders| folders.insert(folder_id, folder));
+
+    if !old_audience.is_empty() {
+        rotate_subtree_keys(folder_id, &old_audience);
+    }
+}
+
+/// Delete an empty folder
+/// Only the owner can delete folders; move or delete their contents first.
+/// Trashed notes still filed in it are taken out of it.
+#[update]
+pub fn delete_folder(folder_id: FolderId) {
+    let caller = msg_caller();
+    let folder = owned_folder(folder_id, &caller);
+
+    let has_live_notes = NOTES.with_borrow(|store| {
+        folder_note_ids(folder_id)
+            .into_iter()
+            .filter_map(|note_id| store.get(&note_id))
+            .any(|note| !note.is_trashed())
+    });
+    if !child_folder_ids(folder_id).is_empty() || has_live_notes {
+        ic_cdk::trap("Folder is not empty");
+    }
+    remove_folder(&folder);
+}
+
+/// Delete every folder of `owner`, children before their parents, returning their IDs
+/// The folders must no longer contain notes
+pub fn delete_folders_of(owner: Principal) -> Vec<FolderId> {
+    let roots: Vec<Folder> = load_folders(OWNER_FOLDERS.with_borrow(|index| {
+        index
+            .keys_range((owner, 0)..=(owner, FolderId::MAX))
+            .map(|(_, folder_id)| folder_id)
+            .collect()
+    }))
+    .into_iter()
+    .filter(|folder| folder.parent_id.is_none())
+    .collect();
+
+    let mut deleted = Vec::new();
+    for root in roots {
+        for folder in load_folders(subtree_folder_ids(root.id)).iter().rev() {
+            remove_folder(folder);
+            deleted.push(folder.id);
+        }
+    }
+    deleted
+}
+
+/// Revoke every folder grant held by `user`, returning the affected folders
+pub fn revoke_folder_grants(user: Principal) -> Vec<FolderId> {
+    let folder_ids: Vec<FolderId> = FOLDER_GRANTEES.with_borrow(|index| {
+        index
+            .keys_range((user, 0)..=(user, FolderId::MAX))
+            .map(|(_, folder_id)| folder_id)
+            .collect()
+    });
+
+    for folder in load_folders(folder_ids.clone()) {
+        remove_grantee(folder, user);
+    }
+    folder_ids
+}
+
+/// File a note in a folder, or take it out of any folder with `None`
+/// Only the owner can file notes, and only in their own folders; workspace
+/// notes cannot be filed
+#[update]
+pub fn move_note_to_folder(note_id: NoteId, folder_id: Option<FolderId>) {
+    let caller = msg_caller();
+
+    if let Some(folder_id) = folder_id {
+        owned_folder(folder_id, &caller);
+    }
+    file_note(note_id, folder_id, caller);
+}
+
+/// File `owner`'s note in `folder_id`, or take it out of any folder with `None`
+/// The folder, if any, must already be checked to belong to `owner`
+pub fn file_note(note_id: NoteId, folder_id: Option<FolderId>, owner: Principal) {
+    NOTES.with_borrow_mut(|store| {
+        let mut note = store
+            .get(&note_id)
+            .unwrap_or_else(|| ic_cdk::trap("Note not found"));
+        if note.owner != owner {
+            ic_cdk::trap("Only owner can move notes between folders");
+        }
+        if note.workspace_id.is_some() {
+            ic_cdk::trap("Workspace notes cannot be filed in folders");
+        }
+        if note.is_trashed() {
+            ic_cdk::trap("Note is in the trash");
+        }
+        if note.folder_id == folder_id {
+            return;
+        }
+
+        let before = note.clone();
+        if note.folder_id.is_some_and(is_shared_through) {
+            note.key_epoch += 1;
+        }
+        unindex_note(&note);
+        note.folder_id = folder_id;
+        index_note(&note);
+        record_note_change(Some(&before), &note, ChangeKind::AccessChanged);
+        store.insert(note_id, note);
+    });
+}
+
+/// Share a folder, and every note beneath it, with a user
+/// Replaces the user's previous permission on the folder; only the owner can share
+#[update]
+pub fn share_folder(folder_id: FolderId, user: Principal, permission: SharePermission) {
+    let caller = msg_caller();
+    let mut folder = owned_folder(folder_id, &caller);
+
+    if user == caller || user == Principal::anonymous() {
+        ic_cdk::trap("Cannot share a folder with this principal");
+    }
+    if is_blocked(&user, &caller) {
+        ic_cdk::trap("Recipient does not accept shares from you");
+    }
+
+    for existing in [
+        SharePermission::Read,
+        SharePermission::Edit,
+        SharePermission::Comment,
+    ] {
+        folder.grantees_mut(existing).retain(|p| p != &user);
+    }
+    folder.grantees_mut(permission).push(user);
+    folder.updated_at = ic_cdk::api::time();
+
+    FOLDER_GRANTEES.with_borrow_mut(|index| index.insert((user, folder_id), ()));
+    FOLDERS.with_borrow_mut(|folders| folders.insert(folder_id, folder));
+
+    NOTES.with_borrow(|store| {
+        for subfolder_id in subtree_folder_ids(folder_id) {
+            for note_id in folder_note_ids(subfolder_id) {
+                if let Some(note) = store.get(&note_id) {
+                    record_access_change(&note, &[]);
+                }
+            }
+        }
+    });
+}
+
+/// Revoke a user's grant on a folder
+/// Only the owner can unshare; the folder's subtree moves to a new key epoch
+#[update]
+pub fn unshare_folder(folder_id: FolderId, user: Principal) {
+    let caller = msg_caller();
+    let folder = owned_folder(folder_id, &caller);
+
+    if folder.permission_of(&user).is_none() {
+        return;
+    }
+    remove_grantee(folder, user);
+}
+
+/// Get a folder by ID
+/// Visible to its owner and to anyone it or an ancestor is shared with
+#[query]
+pub fn get_folder(folder_id: FolderId) -> Option<Folder> {
+    let caller = msg_caller();
+
+    let folder = FOLDERS.with_borrow(|folders| folders.get(&folder_id))?;
+    can_open(&folder, &caller).then_some(folder)
+}
+
+/// Get every folder owned by the caller
+#[query]
+pub fn get_my_folders() -> Vec<Folder> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let folder_ids: Vec<FolderId> = OWNER_FOLDERS.with_borrow(|index| {
+        index
+            .keys_range((caller, 0)..=(caller, FolderId::MAX))
+            .map(|(_, folder_id)| folder_id)
+            .collect()
+    });
+    load_folders(folder_ids)
+}
+
+/// Get the folders shared directly with the caller
+#[query]
+pub fn get_shared_folders() -> Vec<Folder> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let folder_ids: Vec<FolderId> = FOLDER_GRANTEES.with_borrow(|index| {
+        index
+            .keys_range((caller, 0)..=(caller, FolderId::MAX))
+            .map(|(_, folder_id)| folder_id)
+            .collect()
+    });
+    load_folders(folder_ids)
+}
+
+/// Get the folders directly inside `folder_id`, or the caller's top-level folders for `None`
+#[query]
+pub fn list_subfolders(folder_id: Option<FolderId>) -> Vec<Folder> {
+    let caller = msg_caller();
+
+    match folder_id {
+        Some(folder_id) => {
+            assert_can_open(folder_id, &caller);
+            load_folders(child_folder_ids(folder_id))
+        }
+        None => get_my_folders()
+            .into_iter()
+            .filter(|folder| folder.parent_id.is_none())
+            .collect(),
+    }
+}
+
+/// List one page of the notes filed directly in a folder, ordered by note ID
+/// Trashed notes are not listed
+#[query]
+pub fn list_folder_notes(folder_id: FolderId, request: PageRequest) -> Page<Note> {
+    let caller = msg_caller();
+    assert_can_open(folder_id, &caller);
+
+    notes_page(folder_note_ids(folder_id), &request, |note| {
+        note.can_read(&caller)
+    })
+}
+
+/// Get the encrypted symmetric key for a folder's name
+/// Derives the latest key epoch unless `key_epoch` is given
+#[update]
+pub async fn encrypted_symmetric_key_for_folder(
+    folder_id: FolderId,
+    key_epoch: Option<u64>,
+    transport_public_key: Vec<u8>,
+) -> String {
+    let caller = msg_caller();
+
+    let folder = FOLDERS
+        .with_borrow(|folders| folders.get(&folder_id))
+        .unwrap_or_else(|| ic_cdk::trap("Folder not found"));
+    if !can_open(&folder, &caller) {
+        ic_cdk::trap(format!(
+            "unauthorized key request by user {}",
+            caller.to_text()
+        ));
+    }
+    let key_epoch = key_epoch.unwrap_or(folder.key_epoch);
+    if key_epoch > folder.key_epoch {
+        ic_cdk::trap(format!(
+            "key epoch {key_epoch} does not exist for folder {folder_id}"
+        ));
+    }
+
+    derive_encrypted_key(
+        folder_key_derivation_input(&folder, key_epoch),
+        transport_public_key,
+    )
+    .await
+}
+
+/// vetKD derivation input for a folder name: `folder_id || "folder" || owner`,
+/// followed by the epoch once it has been rotated
+fn folder_key_derivation_input(folder: &Folder, key_epoch: u64) -> Vec<u8> {
+    let mut buf = vec![];
+    buf.extend_from_slice(&folder.id.to_be_bytes());
+    buf.extend_from_slice(b"folder");
+    buf.extend_from_slice(folder.owner.as_slice());
+    if key_epoch > 0 {
+        buf.extend_from_slice(&key_epoch.to_be_bytes());
+    }
+    buf
+}
+
+/// `folder_id` followed by its ancestors, nearest first
+fn ancestors(folder_id: FolderId) -> Vec<Folder> {
+    let mut chain: Vec<Folder> = Vec::new();
+    let mut next = Some(folder_id);
+
+    FOLDERS.with_borrow(|folders| {
+        while let Some(folder_id) = next {
+            if chain.len() >= MAX_FOLDER_DEPTH {
+                break;
+            }
+            let Some(folder) = folders.get(&folder_id) else {
+                break;
+            };
+            next = folder.parent_id;
+            chain.push(folder);
+        }
+    });
+    chain
+}
+
+fn child_folder_ids(folder_id: FolderId) -> Vec<FolderId> {
+    FOLDER_CHILDREN.with_borrow(|children| {
+        children
+            .keys_range((folder_id, 0)..=(folder_id, FolderId::MAX))
+            .map(|(_, child_id)| child_id)
+            .collect()
+    })
+}
+
+/// Number of levels in the subtree rooted at `folder_id`, counting itself
+fn subtree_height(folder_id: FolderId) -> usize {
+    let mut level = vec![folder_id];
+    let mut height = 0;
+    while !level.is_empty() {
+        height += 1;
+        level = level.into_iter().flat_map(child_folder_ids).collect();
+    }
+    height
+}
+
+/// Whether `folder_id` or any of its ancestors is shared with anyone
+fn is_shared_through(folder_id: FolderId) -> bool {
+    ancestors(folder_id).iter().any(Folder::is_shared)
+}
+
+/// Move every folder and filed note beneath `folder_id`, trashed notes
+/// included, to a new key epoch after `revoked` may have lost access to them
+fn rotate_subtree_keys(folder_id: FolderId, revoked: &[Principal]) {
+    for subfolder_id in subtree_folder_ids(folder_id) {
+        FOLDERS.with_borrow_mut(|folders| {
+            if let Some(mut folder) = folders.get(&subfolder_id) {
+                folder.key_epoch += 1;
+                folders.insert(subfolder_id, folder);
+            }
+        });
+        NOTES.with_borrow_mut(|store| {
+            for note_id in folder_note_ids(subfolder_id) {
+                if let Some(mut note) = store.get(&note_id) {
+                    note.key_epoch += 1;
+                    record_access_change(&note, revoked);
+                    store.insert(note_id, note);
+                }
+            }
+        });
+    }
+}
+
+fn remove_folder(folder: &Folder) {
+    let folder_id = folder.id;
+    let shared = is_shared_through(folder_id);
+    NOTES.with_borrow_mut(|store| {
+        for note_id in folder_note_ids(folder_id) {
+            FOLDER_NOTES.with_borrow_mut(|index| index.remove(&(folder_id, note_id)));
+            if let Some(mut note) = store.get(&note_id) {
+                let before = note.clone();
+                note.folder_id = None;
+                if shared {
+                    note.key_epoch += 1;
+                }
+                record_note_change(Some(&before), &note, ChangeKind::AccessChanged);
+                store.insert(note_id, note);
+            }
+        }
+    });
+    FOLDER_GRANTEES.with_borrow_mut(|index| {
+        for user in folder
+            .shared_read
+            .iter()
+            .chain(folder.shared_edit.iter())
+            .chain(folder.shared_comment.iter())
+        {
+            index.remove(&(*user, folder_id));
+        }
+    });
+    if let Some(parent_id) = folder.parent_id {
+        FOLDER_CHILDREN.with_borrow_mut(|children| children.remove(&(parent_id, folder_id)));
+    }
+    OWNER_FOLDERS.with_borrow_mut(|index| index.remove(&(folder.owner, folder_id)));
+    FOLDERS.with_borrow_mut(|folders| folders.remove(&folder_id));
+}
+
+fn remove_grantee(mut folder: Folder, user: Principal) {
+    let folder_id = folder.id;
+    for permission in [
+        SharePermission::Read,
+        SharePermission::Edit,
+        SharePermission::Comment,
+    ] {
+        folder.grantees_mut(permission).retain(|p| p != &user);
+    }
+    folder.updated_at = ic_cdk::api::time();
+
+    FOLDER_GRANTEES.with_borrow_mut(|index| index.remove(&(user, folder_id)));
+    FOLDERS.with_borrow_mut(|folders| folders.insert(folder_id, folder));
+    rotate_subtree_keys(folder_id, &[user]);
+}
+
+fn permission_rank(permission: SharePermission) -> u8 {
+    match permission {
+        SharePermission::Read => 0,
+        SharePermission::Comment => 1,
+        SharePermission::Edit => 2,
+    }
+}
+
+fn can_open(folder: &Folder, principal: &Principal) -> bool {
+    &folder.owner == principal || folder_permission(folder.id, principal).is_some()
+}
+
+fn assert_can_open(folder_id: FolderId, principal: &Principal) {
+    match FOLDERS.with_borrow(|folders| folders.get(&folder_id)) {
+        Some(folder) if can_open(&folder, principal) => {}
+        Some(_) => ic_cdk::trap("Not authorized to open this folder"),
+        None => ic_cdk::trap("Folder not found"),
+    }
+}
+
+fn owned_folder(folder_id: FolderId, caller: &Principal) -> Folder {
+    match FOLDERS.with_borrow(|folders| folders.get(&folder_id)) {
+        Some(folder) if &folder.owner == caller => folder,
+        Some(_) => ic_cdk::trap("Only the folder owner can do this"),
+        None => ic_cdk::trap("Folder not found"),
+    }
+}
+
+fn load_folders(folder_ids: Vec<FolderId>) -> Vec<Folder> {
+    FOLDERS.with_borrow(|folders| {
+        folder_ids
+            .into_iter()
+            .filter_map(|folder_id| folders.get(&folder_id))
+            .collect()
+    })
+}
+
+fn validate_folder_name(encrypted_name: &str) {
+    if encrypted_name.is_empty() || encrypted_name.len() > MAX_FOLDER_NAME_SIZE {
+        ic_cdk::trap(format!(
+            "Encrypted folder name must be between 1 and {} bytes",
+            MAX_FOLDER_NAME_SIZE
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note, principal};
+    use crate::trash::move_to_trash;
+
+    fn folder(id: FolderId, owner: Principal, parent_id: Option<FolderId>) -> Folder {
+        let folder = Folder {
+            id,
+            owner,
+            parent_id,
+            encrypted_name: "name".to_string(),
+            shared_read: vec![],
+            shared_edit: vec![],
+            shared_comment: vec![],
+            key_epoch: 0,
+            name_key_epoch: 0,
+            created_at: 0,
+            updated_at: 0,
+        };
+        if let Some(parent_id) = parent_id {
+            FOLDER_CHILDREN.with_borrow_mut(|children| children.insert((parent_id, id), ()));
+        }
+        FOLDERS.with_borrow_mut(|folders| folders.insert(id, folder.clone()));
+        folder
+    }
+
+    #[test]
+    fn test_folder_grant_is_inherited_by_subfolders() {
+        let (alice, bob) = (principal(1), principal(2));
+        let mut root = folder(1, alice, None);
+        folder(2, alice, Some(1));
+        folder(3, alice, Some(2));
+
+        root.shared_read.push(bob);
+        FOLDERS.with_borrow_mut(|folders| folders.insert(1, root));
+
+        assert_eq!(folder_permission(3, &bob), Some(SharePermission::Read));
+        assert_eq!(folder_permission(3, &alice), None);
+        assert_eq!(subtree_folder_ids(1), vec![1, 2, 3]);
+        assert_eq!(subtree_height(1), 3);
+    }
+
+    #[test]
+    fn test_strongest_folder_grant_wins() {
+        let (alice, bob) = (principal(3), principal(4));
+        let mut outer = folder(10, alice, None);
+        let mut inner = folder(11, alice, Some(10));
+
+        outer.shared_edit.push(bob);
+        inner.shared_read.push(bob);
+        FOLDERS.with_borrow_mut(|folders| {
+            folders.insert(10, outer);
+            folders.insert(11, inner);
+        });
+
+        assert_eq!(folder_permission(11, &bob), Some(SharePermission::Edit));
+        assert!(is_shared_through(11));
+    }
+
+    #[test]
+    fn test_rotation_reaches_trashed_notes_in_the_subtree() {
+        let (alice, bob) = (principal(5), principal(6));
+        folder(20, alice, None);
+        folder(21, alice, Some(20));
+        let mut filed = Note {
+            folder_id: Some(21),
+            ..note(20, alice)
+        };
+        index_note(&filed);
+        move_to_trash(&mut filed);
+        NOTES.with_borrow_mut(|store| store.insert(20, filed));
+
+        rotate_subtree_keys(20, &[bob]);
+
+        let rotated = NOTES.with_borrow(|store| store.get(&20)).unwrap();
+        assert_eq!(rotated.key_epoch, 1);
+    }
+}
@@ -0,0 +1,471 @@
+// Sharing Groups Module
+// src/encrypted-notes-backend/src/groups.rs
+//
+// A group is an owner-defined, named set of principals. Notes store the IDs
+// of the groups they are shared with, and `can_read`/`can_edit` resolve
+// membership through GROUP_MEMBERS at check time, so adding a member grants
+// access to every note shared with the group without rewriting those notes.
+// Removing members or deleting a group is a revocation and rotates the key
+// epoch of the group's notes, like unsharing a single user does. Group shares
+// skip the share inbox, so a principal who blocked the owner can neither be
+// added to their groups nor be reached by sharing with a group they are in.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::audit::record_access;
+use crate::changes::{record_access_change, record_note_change};
+use crate::helpers::{assert_not_anonymous, get_next_id};
+use crate::inbox::is_blocked;
+use crate::index::{group_note_ids, refresh_group};
+use crate::storage::{GROUPS, GROUP_MEMBERS, GROUP_NOTES, GROUP_OWNERS, NOTES};
+use crate::types::{AuditAction, ChangeKind, Group, GroupId, Note, NoteId, SharePermission};
+
+const MAX_GROUP_NAME_LENGTH: usize = 100;
+const MAX_GROUP_MEMBERS: usize = 100;
+
+/// Whether `principal` belongs to any of `group_ids`
+pub fn is_member_of_any(group_ids: &[GroupId], principal: &Principal) -> bool {
+    if group_ids.is_empty() {
+        return false;
+    }
+
+    GROUP_MEMBERS.with_borrow(|members| {
+        group_ids
+            .iter()
+            .any(|group_id| members.contains_key(&(*principal, *group_id)))
+    })
+}
+
+/// Create a group owned by the caller
+/// Returns the new group ID
+#[update]
+pub fn create_group(name: String, members: Vec<Principal>) -> GroupId {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    let name = validate_group_name(&name);
+
+    let group_id = get_next_id();
+    let now = ic_cdk::api::time();
+    let mut group = Group {
+        id: group_id,
+        owner: caller,
+        name,
+        members: vec![],
+        created_at: now,
+        updated_at: now,
+    };
+    add_members(&mut group, members);
+
+    GROUP_OWNERS.with_borrow_mut(|owners| owners.insert((caller, group_id), ()));
+    GROUPS.with_borrow_mut(|groups| groups.insert(group_id, group));
+
+    group_id
+}
+
+/// Rename a group
+/// Only the group owner can rename it
+#[update]
+pub fn rename_group(group_id: GroupId, name: String) {
+    let caller = msg_caller();
+    let mut group = owned_group(group_id, &caller);
+
+    group.name = validate_group_name(&name);
+    group.updated_at = ic_cdk::api::time();
+    GROUPS.with_borrow_mut(|groups| groups.insert(group_id, group));
+}
+
+/// Add members to a group
+/// They gain access to every note shared with the group right away
+#[update]
+pub fn add_group_members(group_id: GroupId, members: Vec<Principal>) {
+    let caller = msg_caller();
+    let mut group = owned_group(group_id, &caller);
+
+    add_members(&mut group, members);
+    group.updated_at = ic_cdk::api::time();
+    GROUPS.with_borrow_mut(|groups| groups.insert(group_id, group));
+
+    NOTES.with_borrow(|store| {
+        for note_id in group_note_ids(group_id) {
+            if let Some(note) = store.get(&note_id) {
+                record_access_change(&note, &[]);
+            }
+        }
+    });
+}
+
+/// Remove members from a group
+/// They lose access right away and the group's notes move to a new key epoch
+#[update]
+pub fn remove_group_members(group_id: GroupId, members: Vec<Principal>) {
+    let caller = msg_caller();
+    let mut group = owned_group(group_id, &caller);
+
+    let removed: Vec<Principal> = group
+        .members
+        .iter()
+        .filter(|member| members.contains(member))
+        .copied()
+        .collect();
+    if removed.is_empty() {
+        return;
+    }
+    group.members.retain(|member| !removed.contains(member));
+
+    GROUP_MEMBERS.with_borrow_mut(|index| {
+        for member in &members {
+            index.remove(&(*member, group_id));
+        }
+    });
+    group.updated_at = ic_cdk::api::time();
+    GROUPS.with_borrow_mut(|groups| groups.insert(group_id, group));
+
+    rotate_group_notes(group_id, &removed);
+}
+
+/// Delete a group and unshare every note shared with it
+/// Only the group owner can delete it
+#[update]
+pub fn delete_group(group_id: GroupId) {
+    let caller = msg_caller();
+    let group = owned_group(group_id, &caller);
+    remove_group(&group);
+}
+
+/// Delete every group `owner` owns, returning their IDs
+pub fn delete_groups_of(owner: Principal) -> Vec<GroupId> {
+    let groups = load_groups(owned_group_ids(owner));
+    for group in &groups {
+        remove_group(group);
+    }
+    groups.iter().map(|group| group.id).collect()
+}
+
+/// Remove `member` from every group they belong to, returning those groups
+/// The groups' notes move to a new key epoch
+pub fn leave_groups(member: Principal) -> Vec<GroupId> {
+    let group_ids: Vec<GroupId> = GROUP_MEMBERS.with_borrow(|members| {
+        members
+            .keys_range((member, 0)..=(member, GroupId::MAX))
+            .map(|(_, group_id)| group_id)
+            .collect()
+    });
+
+    for group_id in &group_ids {
+        GROUP_MEMBERS.with_borrow_mut(|index| index.remove(&(member, *group_id)));
+        GROUPS.with_borrow_mut(|groups| {
+            if let Some(mut group) = groups.get(group_id) {
+                group.members.retain(|m| m != &member);
+                group.updated_at = ic_cdk::api::time();
+                groups.insert(*group_id, group);
+            }
+        });
+        rotate_group_notes(*group_id, &[member]);
+    }
+    group_ids
+}
+
+/// Get a group by ID
+/// Visible to the group owner and its members
+#[query]
+pub fn get_group(group_id: GroupId) -> Option<Group> {
+    let caller = msg_caller();
+
+    GROUPS
+        .with_borrow(|groups| groups.get(&group_id))
+        .filter(|group| group.owner == caller || group.members.contains(&caller))
+}
+
+/// Get the groups owned by the caller
+#[query]
+pub fn get_my_groups() -> Vec<Group> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    load_groups(owned_group_ids(caller))
+}
+
+/// Get the groups the caller is a member of
+#[query]
+pub fn get_group_memberships() -> Vec<Group> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let group_ids: Vec<GroupId> = GROUP_MEMBERS.with_borrow(|members| {
+        members
+            .keys_range((caller, 0)..=(caller, GroupId::MAX))
+            .map(|(_, group_id)| group_id)
+            .collect()
+    });
+    load_groups(group_ids)
+}
+
+/// Share a note with every member of a group
+/// Only the owner can share, and only with a group they own
+#[update]
+pub fn share_note_with_group(note_id: NoteId, group_id: GroupId, permission: SharePermission) {
+    let caller = msg_caller();
+    let group = owned_group(group_id, &caller);
+
+    if group
+        .members
+        .iter()
+        .any(|member| is_blocked(member, &caller))
+    {
+        ic_cdk::trap("A member of this group does not accept shares from you");
+    }
+
+    NOTES.with_borrow_mut(|store| {
+        let mut note = store
+            .get(&note_id)
+            .unwrap_or_else(|| ic_cdk::trap("Note not found"));
+        if !note.is_managed_by(&caller) {
+            ic_cdk::trap("Only owner can share");
+        }
+        if note.is_trashed() {
+            ic_cdk::trap("Note is in the trash");
+        }
+
+        let group_ids = note.group_ids_mut(permission);
+        if !group_ids.contains(&group_id) {
+            group_ids.push(group_id);
+        }
+        refresh_group(&note, group_id);
+        record_note_change(None, &note, ChangeKind::AccessChanged);
+        record_access(
+            note_id,
+            caller,
+            AuditAction::GroupShare {
+                group_id,
+                permission,
+            },
+        );
+        store.insert(note_id, note);
+    });
+}
+
+/// Stop sharing a note with a group at `permission` level
+/// Only the owner can unshare; rotates the note's key epoch
+#[update]
+pub fn unshare_note_from_group(note_id: NoteId, group_id: GroupId, permission: SharePermission) {
+    let caller = msg_caller();
+
+    NOTES.with_borrow_mut(|store| {
+        let mut note = store
+            .get(&note_id)
+            .unwrap_or_else(|| ic_cdk::trap("Note not found"));
+        if !note.is_managed_by(&caller) {
+            ic_cdk::trap("Only owner can unshare");
+        }
+        if note.is_trashed() {
+            ic_cdk::trap("Note is in the trash");
+        }
+
+        let before = note.clone();
+        let had_group = note.group_ids(permission).contains(&group_id);
+        note.group_ids_mut(permission).retain(|id| id != &group_id);
+        refresh_group(&note, group_id);
+        if had_group {
+            note.key_epoch += 1;
+        }
+        record_note_change(Some(&before), &note, ChangeKind::AccessChanged);
+        record_access(
+            note_id,
+            caller,
+            AuditAction::GroupUnshare {
+                group_id,
+                permission,
+            },
+        );
+        store.insert(note_id, note);
+    });
+}
+
+fn validate_group_name(name: &str) -> String {
+    let name = name.trim();
+    if name.is_empty() || name.len() > MAX_GROUP_NAME_LENGTH {
+        ic_cdk::trap(format!(
+            "Group name must be between 1 and {} characters",
+            MAX_GROUP_NAME_LENGTH
+        ));
+    }
+    name.to_string()
+}
+
+fn add_members(group: &mut Group, members: Vec<Principal>) {
+    for member in members {
+        if member == Principal::anonymous() || member == group.owner {
+            continue;
+        }
+        if is_blocked(&member, &group.owner) {
+            ic_cdk::trap(format!("{} does not accept shares from you", member));
+        }
+        if !group.members.contains(&member) {
+            group.members.push(member);
+        }
+    }
+    if group.members.len() > MAX_GROUP_MEMBERS {
+        ic_cdk::trap(format!(
+            "Groups can have at most {} members",
+            MAX_GROUP_MEMBERS
+        ));
+    }
+
+    GROUP_MEMBERS.with_borrow_mut(|index| {
+        for member in &group.members {
+            index.insert((*member, group.id), ());
+        }
+    });
+}
+
+fn owned_group(group_id: GroupId, caller: &Principal) -> Group {
+    match GROUPS.with_borrow(|groups| groups.get(&group_id)) {
+        Some(group) if &group.owner == caller => group,
+        Some(_) => ic_cdk::trap("Only the group owner can manage this group"),
+        None => ic_cdk::trap("Group not found"),
+    }
+}
+
+/// Drop shares with groups that no longer exist from `note`
+pub fn drop_missing_groups(note: &mut Note) {
+    GROUPS.with_borrow(|groups| {
+        note.shared_read_groups.retain(|id| groups.contains_key(id));
+        note.shared_edit_groups.retain(|id| groups.contains_key(id));
+        note.shared_comment_groups
+            .retain(|id| groups.contains_key(id));
+    });
+}
+
+fn owned_group_ids(owner: Principal) -> Vec<GroupId> {
+    GROUP_OWNERS.with_borrow(|owners| {
+        owners
+            .keys_range((owner, 0)..=(owner, GroupId::MAX))
+            .map(|(_, group_id)| group_id)
+            .collect()
+    })
+}
+
+fn load_groups(group_ids: Vec<GroupId>) -> Vec<Group> {
+    GROUPS.with_borrow(|groups| {
+        group_ids
+            .into_iter()
+            .filter_map(|group_id| groups.get(&group_id))
+            .collect()
+    })
+}
+
+/// Unshare every note shared with `group`, then drop the group and its indexes
+fn remove_group(group: &Group) {
+    let group_id = group.id;
+    let note_ids = group_note_ids(group_id);
+
+    NOTES.with_borrow_mut(|store| {
+        for note_id in &note_ids {
+            if let Some(mut note) = store.get(note_id) {
+                note.shared_read_groups.retain(|id| id != &group_id);
+                note.shared_edit_groups.retain(|id| id != &group_id);
+                note.shared_comment_groups.retain(|id| id != &group_id);
+                note.key_epoch += 1;
+                record_access_change(&note, &group.members);
+                store.insert(*note_id, note);
+            }
+        }
+    });
+    GROUP_NOTES.with_borrow_mut(|index| {
+        for note_id in &note_ids {
+            index.remove(&(group_id, *note_id));
+        }
+    });
+    GROUP_MEMBERS.with_borrow_mut(|index| {
+        for member in &group.members {
+            index.remove(&(*member, group_id));
+        }
+    });
+    GROUP_OWNERS.with_borrow_mut(|owners| owners.remove(&(group.owner, group_id)));
+    GROUPS.with_borrow_mut(|groups| groups.remove(&group_id));
+}
+
+/// Move every note shared with a group to a new key epoch after `removed` left it
+fn rotate_group_notes(group_id: GroupId, removed: &[Principal]) {
+    NOTES.with_borrow_mut(|store| {
+        for note_id in group_note_ids(group_id) {
+            if let Some(mut note) = store.get(&note_id) {
+                note.key_epoch += 1;
+                record_access_change(&note, removed);
+                store.insert(note_id, note);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::index_note;
+    use crate::test_utils::{note, principal};
+    use crate::trash::move_to_trash;
+
+    #[test]
+    fn test_removing_a_group_unshares_its_notes() {
+        let (alice, bob) = (principal(1), principal(2));
+        let group = Group {
+            id: 7,
+            owner: alice,
+            name: "team".to_string(),
+            members: vec![bob],
+            created_at: 0,
+            updated_at: 0,
+        };
+        GROUPS.with_borrow_mut(|groups| groups.insert(7, group.clone()));
+        GROUP_OWNERS.with_borrow_mut(|owners| owners.insert((alice, 7), ()));
+        GROUP_MEMBERS.with_borrow_mut(|members| members.insert((bob, 7), ()));
+        let shared = Note {
+            shared_read_groups: vec![7],
+            shared_comment_groups: vec![7],
+            ..note(1, alice)
+        };
+        index_note(&shared);
+        NOTES.with_borrow_mut(|store| store.insert(1, shared));
+
+        remove_group(&group);
+
+        let unshared = NOTES.with_borrow(|store| store.get(&1)).unwrap();
+        assert!(unshared.shared_read_groups.is_empty());
+        assert!(unshared.shared_comment_groups.is_empty());
+        assert_eq!(unshared.key_epoch, 1);
+        assert!(!unshared.can_read(&bob));
+        assert!(group_note_ids(7).is_empty());
+        assert!(!is_member_of_any(&[7], &bob));
+        assert!(GROUPS.with_borrow(|groups| !groups.contains_key(&7)));
+    }
+
+    #[test]
+    fn test_removing_a_group_unshares_its_trashed_notes() {
+        let (alice, bob) = (principal(3), principal(4));
+        let group = Group {
+            id: 8,
+            owner: alice,
+            name: "team".to_string(),
+            members: vec![bob],
+            created_at: 0,
+            updated_at: 0,
+        };
+        GROUPS.with_borrow_mut(|groups| groups.insert(8, group.clone()));
+        let mut shared = Note {
+            shared_edit_groups: vec![8],
+            ..note(2, alice)
+        };
+        index_note(&shared);
+        move_to_trash(&mut shared);
+        NOTES.with_borrow_mut(|store| store.insert(2, shared));
+
+        remove_group(&group);
+
+        let unshared = NOTES.with_borrow(|store| store.get(&2)).unwrap();
+        assert!(unshared.shared_edit_groups.is_empty());
+        assert_eq!(unshared.key_epoch, 1);
+        assert!(group_note_ids(8).is_empty());
+    }
+}
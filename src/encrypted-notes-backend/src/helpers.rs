@@ -5,7 +5,17 @@ use ic_cdk::{query};
 use ic_cdk::management_canister::{VetKDCurve, VetKDKeyId};
 
 use crate::storage::{NEXT_ID, MAX_NOTE_SIZE, get_ledger_ids};
-use crate::types::{NoteId, Account};
+use crate::types::{NoteId, Account, Page, PageRequest};
+
+/// Current IC time in nanoseconds
+/// Unit tests run outside a canister, where they see a fixed clock instead
+pub fn now() -> u64 {
+    if cfg!(test) {
+        0
+    } else {
+        ic_cdk::api::time()
+    }
+}
 
 // Note size management functions
 
@@ -86,6 +96,79 @@ pub fn get_memory_stats() -> (u64, u64, usize, usize) {
 /// Satoshis per BTC conversion factor
 pub const SATS_PER_BTC: u64 = 100_000_000;
 
+// Pagination helpers
+
+/// Page size used when a request does not specify a limit
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+/// Upper bound on page size to stay well below the IC response size limit
+pub const MAX_PAGE_SIZE: u32 = 200;
+
+/// Effective number of items for a page request
+pub fn page_size(request: &PageRequest) -> usize {
+    request
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE) as usize
+}
+
+/// Parse the request cursor into the key type of the listing
+/// Traps if the cursor was not produced by the same listing
+pub fn parse_cursor<K: std::str::FromStr>(request: &PageRequest) -> Option<K> {
+    request.cursor.as_ref().map(|cursor| {
+        cursor
+            .parse()
+            .unwrap_or_else(|_| ic_cdk::trap(format!("Invalid page cursor: {}", cursor)))
+    })
+}
+
+/// Build a page from `(key, item)` entries that already start after the cursor
+/// Only consumes one entry beyond the page to detect whether more remain
+pub fn paginate<K: ToString, T>(
+    entries: impl Iterator<Item = (K, T)>,
+    request: &PageRequest,
+) -> Page<T> {
+    let limit = page_size(request);
+    let mut items = Vec::with_capacity(limit);
+    let mut last_key = None;
+    let mut has_more = false;
+
+    for (key, item) in entries {
+        if items.len() == limit {
+            has_more = true;
+            break;
+        }
+        last_key = Some(key);
+        items.push(item);
+    }
+
+    Page {
+        items,
+        next_cursor: if has_more {
+            last_key.map(|key| key.to_string())
+        } else {
+            None
+        },
+    }
+}
+
+/// Drain a paged listing into a single Vec, for the legacy unpaged endpoints
+pub fn collect_pages<T>(mut fetch: impl FnMut(PageRequest) -> Page<T>) -> Vec<T> {
+    let mut items = Vec::new();
+    let mut cursor = None;
+
+    loop {
+        let page = fetch(PageRequest {
+            cursor,
+            limit: Some(MAX_PAGE_SIZE),
+        });
+        items.extend(page.items);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => return items,
+        }
+    }
+}
+
 pub fn get_next_id() -> NoteId {
     NEXT_ID.with_borrow_mut(|id| {
         let next = *id.get();
@@ -233,3 +316,48 @@ pub async fn transfer_ckbtc(
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetch(all: &[u64], request: PageRequest) -> Page<u64> {
+        let after: Option<u64> = request.cursor.as_ref().map(|c| c.parse().unwrap());
+        paginate(
+            all.iter()
+                .copied()
+                .filter(|id| after.is_none_or(|c| *id > c))
+                .map(|id| (id, id)),
+            &request,
+        )
+    }
+
+    #[test]
+    fn test_paginate_sets_cursor_only_when_more_remain() {
+        let all: Vec<u64> = (1..=5).collect();
+
+        let first = fetch(&all, PageRequest { cursor: None, limit: Some(2) });
+        assert_eq!(first.items, vec![1, 2]);
+        assert_eq!(first.next_cursor.as_deref(), Some("2"));
+
+        let last = fetch(&all, PageRequest { cursor: Some("4".to_string()), limit: Some(2) });
+        assert_eq!(last.items, vec![5]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn test_collect_pages_returns_everything() {
+        let all: Vec<u64> = (1..=(MAX_PAGE_SIZE as u64 * 2 + 3)).collect();
+        assert_eq!(collect_pages(|request| fetch(&all, request)), all);
+    }
+
+    #[test]
+    fn test_page_size_is_clamped() {
+        assert_eq!(page_size(&PageRequest::default()), DEFAULT_PAGE_SIZE as usize);
+        assert_eq!(page_size(&PageRequest { cursor: None, limit: Some(0) }), 1);
+        assert_eq!(
+            page_size(&PageRequest { cursor: None, limit: Some(u32::MAX) }),
+            MAX_PAGE_SIZE as usize
+        );
+    }
+}
@@ -0,0 +1,326 @@
+// Share Inbox Module
+// src/encrypted-notes-backend/src/inbox.rs
+//
+// Sharing a note with a principal no longer grants access straight away: the
+// share lands in the recipient's inbox, and only an accepted share is added
+// to the note's grant lists that `can_read`/`can_edit` consult. Recipients can
+// decline offers and block principals, whose shares are then rejected with
+// `ShareError::Blocked`. Changing the permission or expiry of a grant the
+// recipient already accepted takes effect immediately. Redeemed invites are
+// accepted by the redeemer's own call and skip the inbox.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::audit::record_access;
+use crate::changes::record_note_change;
+use crate::helpers::assert_not_anonymous;
+use crate::note::apply_grant;
+use crate::storage::{BLOCKLIST, NOTES, NOTE_OFFERS, SHARE_INBOX};
+use crate::types::{
+    AuditAction, ChangeKind, Note, NoteId, PendingShare, ShareError, SharePermission,
+};
+
+/// Put `offer` in the inbox of `user`, or update the grant directly if they already accepted one
+/// Callers are responsible for authorization and for storing the note
+pub fn offer_share(
+    note: &mut Note,
+    user: Principal,
+    offer: PendingShare,
+) -> Result<(), ShareError> {
+    if is_blocked(&user, &offer.from) {
+        return Err(ShareError::Blocked);
+    }
+
+    if has_direct_access(note, &user) {
+        apply_grant(note, user, offer.permission, offer.expires_at);
+        return Ok(());
+    }
+
+    insert_offer(user, note.id, offer);
+    Ok(())
+}
+
+/// Withdraw a pending offer of `permission` on `note_id` to `user`, if any
+pub fn withdraw_offer(note_id: NoteId, user: Principal, permission: SharePermission) {
+    let offered = SHARE_INBOX.with_borrow(|inbox| inbox.get(&(user, note_id)));
+    if matches!(offered, Some(offer) if offer.permission == permission) {
+        remove_offer(user, note_id);
+    }
+}
+
+/// Remove every pending offer of a note
+pub fn clear_pending_shares(note_id: NoteId) {
+    let recipients: Vec<Principal> = NOTE_OFFERS.with_borrow(|offers| {
+        offers
+            .keys_range((note_id, Principal::management_canister())..)
+            .take_while(|(id, _)| *id == note_id)
+            .map(|(_, user)| user)
+            .collect()
+    });
+
+    for user in recipients {
+        remove_offer(user, note_id);
+    }
+}
+
+/// Populate the note-keyed offer index from the inboxes if it has never been built
+/// Called after upgrades so canisters that predate the index migrate
+pub fn rebuild_offer_index() {
+    let indexed = NOTE_OFFERS.with_borrow(|offers| !offers.is_empty());
+    if indexed {
+        return;
+    }
+
+    SHARE_INBOX.with_borrow(|inbox| {
+        NOTE_OFFERS.with_borrow_mut(|offers| {
+            for (user, note_id) in inbox.keys() {
+                offers.insert((note_id, user), ());
+            }
+        })
+    });
+}
+
+/// Drop the pending offers to `user` and their blocklist
+pub fn forget_user(user: Principal) {
+    let offered: Vec<NoteId> = SHARE_INBOX.with_borrow(|inbox| {
+        inbox
+            .keys_range(principal_range(user))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    });
+    for note_id in offered {
+        remove_offer(user, note_id);
+    }
+
+    let blocked = get_blocked(user);
+    BLOCKLIST.with_borrow_mut(|blocklist| {
+        for other in blocked {
+            blocklist.remove(&(user, other));
+        }
+    });
+}
+
+/// Whether `user` has blocked `from`
+pub fn is_blocked(user: &Principal, from: &Principal) -> bool {
+    BLOCKLIST.with_borrow(|blocklist| blocklist.contains_key(&(*user, *from)))
+}
+
+/// Get the shares offered to the caller that are still valid
+#[query]
+pub fn get_share_inbox() -> Vec<PendingShare> {
+    let caller = msg_caller();
+    let now = ic_cdk::api::time();
+
+    SHARE_INBOX
+        .with_borrow(|inbox| {
+            inbox
+                .range(principal_range(caller))
+                .map(|(_, offer)| offer)
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .filter(|offer| offer.expires_at.is_none_or(|expires_at| expires_at > now))
+        .filter(|offer| {
+            NOTES.with_borrow(|store| {
+                store
+                    .get(&offer.note_id)
+                    .is_some_and(|note| !note.is_trashed() && note.is_managed_by(&offer.from))
+            })
+        })
+        .collect()
+}
+
+/// Accept a pending share, gaining its permission on the note
+#[update]
+pub fn accept_share(note_id: NoteId) {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let offer = take_offer(caller, note_id);
+    if let Some(expires_at) = offer.expires_at {
+        if expires_at <= ic_cdk::api::time() {
+            ic_cdk::trap("Share has expired");
+        }
+    }
+
+    NOTES.with_borrow_mut(|store| {
+        let mut note = store
+            .get(&note_id)
+            .unwrap_or_else(|| ic_cdk::trap("Note not found"));
+        if note.is_trashed() {
+            ic_cdk::trap("Note is in the trash");
+        }
+        if !note.is_managed_by(&offer.from) {
+            ic_cdk::trap("Share is no longer valid");
+        }
+
+        apply_grant(&mut note, caller, offer.permission, offer.expires_at);
+        record_note_change(None, &note, ChangeKind::AccessChanged);
+        record_access(
+            note_id,
+            caller,
+            AuditAction::ShareAccepted {
+                permission: offer.permission,
+            },
+        );
+        store.insert(note_id, note);
+    });
+}
+
+/// Decline a pending share
+#[update]
+pub fn decline_share(note_id: NoteId) {
+    let caller = msg_caller();
+
+    let offer = take_offer(caller, note_id);
+    if NOTES.with_borrow(|store| store.contains_key(&note_id)) {
+        record_access(
+            note_id,
+            caller,
+            AuditAction::ShareDeclined {
+                permission: offer.permission,
+            },
+        );
+    }
+}
+
+/// Reject all future shares from `user` and drop their pending offers
+/// Grants the caller already accepted are kept
+#[update]
+pub fn block_user(user: Principal) {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    if user == caller {
+        ic_cdk::trap("Cannot block yourself");
+    }
+
+    BLOCKLIST.with_borrow_mut(|blocklist| blocklist.insert((caller, user), ()));
+
+    let offered: Vec<NoteId> = SHARE_INBOX.with_borrow(|inbox| {
+        inbox
+            .range(principal_range(caller))
+            .filter(|(_, offer)| offer.from == user)
+            .map(|((_, note_id), _)| note_id)
+            .collect()
+    });
+    for note_id in offered {
+        remove_offer(caller, note_id);
+    }
+}
+
+/// Accept shares from `user` again
+#[update]
+pub fn unblock_user(user: Principal) {
+    let caller = msg_caller();
+    BLOCKLIST.with_borrow_mut(|blocklist| blocklist.remove(&(caller, user)));
+}
+
+/// Get the principals the caller has blocked
+#[query]
+pub fn get_blocked_users() -> Vec<Principal> {
+    get_blocked(msg_caller())
+}
+
+fn principal_range(principal: Principal) -> std::ops::RangeInclusive<(Principal, NoteId)> {
+    (principal, 0)..=(principal, NoteId::MAX)
+}
+
+fn get_blocked(user: Principal) -> Vec<Principal> {
+    BLOCKLIST.with_borrow(|blocklist| {
+        blocklist
+            .keys_range((user, Principal::management_canister())..)
+            .take_while(|(blocker, _)| *blocker == user)
+            .map(|(_, blocked)| blocked)
+            .collect()
+    })
+}
+
+fn take_offer(user: Principal, note_id: NoteId) -> PendingShare {
+    remove_offer(user, note_id).unwrap_or_else(|| ic_cdk::trap("No pending share for this note"))
+}
+
+fn insert_offer(user: Principal, note_id: NoteId, offer: PendingShare) {
+    SHARE_INBOX.with_borrow_mut(|inbox| inbox.insert((user, note_id), offer));
+    NOTE_OFFERS.with_borrow_mut(|offers| offers.insert((note_id, user), ()));
+}
+
+fn remove_offer(user: Principal, note_id: NoteId) -> Option<PendingShare> {
+    NOTE_OFFERS.with_borrow_mut(|offers| offers.remove(&(note_id, user)));
+    SHARE_INBOX.with_borrow_mut(|inbox| inbox.remove(&(user, note_id)))
+}
+
+fn has_direct_access(note: &Note, user: &Principal) -> bool {
+    &note.owner == user
+        || [
+            SharePermission::Read,
+            SharePermission::Edit,
+            SharePermission::Comment,
+        ]
+        .iter()
+        .any(|permission| note.grantees(*permission).contains(user))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note, principal};
+
+    fn offer(note_id: NoteId, from: Principal, permission: SharePermission) -> PendingShare {
+        PendingShare {
+            note_id,
+            from,
+            permission,
+            expires_at: None,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_blocked_sender_is_rejected() {
+        let (alice, bob) = (principal(1), principal(2));
+        BLOCKLIST.with_borrow_mut(|blocklist| blocklist.insert((bob, alice), ()));
+        let mut shared = note(1, alice);
+
+        assert_eq!(
+            offer_share(&mut shared, bob, offer(1, alice, SharePermission::Read)),
+            Err(ShareError::Blocked)
+        );
+        assert!(shared.shared_read.is_empty());
+    }
+
+    #[test]
+    fn test_accepted_grant_is_updated_in_place() {
+        let (alice, bob) = (principal(3), principal(4));
+        let mut shared = note(2, alice);
+        shared.shared_read.push(bob);
+
+        offer_share(&mut shared, bob, offer(1, alice, SharePermission::Edit)).unwrap();
+
+        assert!(shared.can_edit(&bob));
+        assert!(SHARE_INBOX.with_borrow(|inbox| !inbox.contains_key(&(bob, 2))));
+    }
+
+    #[test]
+    fn test_clear_pending_shares_only_touches_one_note() {
+        let (alice, bob, carol) = (principal(5), principal(6), principal(7));
+        let (mut first, mut second) = (note(3, alice), note(4, alice));
+        offer_share(&mut first, bob, offer(3, alice, SharePermission::Read)).unwrap();
+        offer_share(&mut first, carol, offer(3, alice, SharePermission::Read)).unwrap();
+        offer_share(&mut second, bob, offer(4, alice, SharePermission::Read)).unwrap();
+
+        clear_pending_shares(3);
+
+        SHARE_INBOX.with_borrow(|inbox| {
+            assert!(!inbox.contains_key(&(bob, 3)));
+            assert!(!inbox.contains_key(&(carol, 3)));
+            assert!(inbox.contains_key(&(bob, 4)));
+        });
+        NOTE_OFFERS.with_borrow(|offers| {
+            assert!(!offers.contains_key(&(3, bob)));
+            assert!(offers.contains_key(&(4, bob)));
+        });
+    }
+}
@@ -0,0 +1,308 @@
+// Note Index Module
+// src/encrypted-notes-backend/src/index.rs
+//
+// Secondary indexes over NOTES keyed by (Principal, NoteId) so listing
+// endpoints can range-scan a single principal instead of every note. Notes
+// shared with a group are indexed by (GroupId, NoteId), workspace notes by
+// (WorkspaceId, NoteId) and filed notes by (FolderId, NoteId); they reach
+// members and folder grantees through their memberships and folder grants.
+// A trashed note leaves only the owner index, so revoking a grant, group,
+// workspace membership or folder share still reaches it.
+
+use candid::Principal;
+
+use crate::folders::subtree_folder_ids;
+use crate::storage::{
+    FOLDER_GRANTEES, FOLDER_NOTES, GRANTEE_INDEX, GROUP_MEMBERS, GROUP_NOTES, NOTES, OWNER_INDEX,
+    WORKSPACE_MEMBERS, WORKSPACE_NOTES,
+};
+use crate::types::{FolderId, GroupId, Note, NoteId, WorkspaceId};
+
+fn principal_range(principal: Principal) -> std::ops::RangeInclusive<(Principal, NoteId)> {
+    (principal, 0)..=(principal, NoteId::MAX)
+}
+
+/// Add the owner and every grantee of `note` to the indexes
+pub fn index_note(note: &Note) {
+    OWNER_INDEX.with_borrow_mut(|index| index.insert((note.owner, note.id), ()));
+    index_grants(note);
+}
+
+/// Take `note` out of its owner's listing when it moves to the trash
+/// Its grantee, group, workspace and folder entries stay until it is erased
+pub fn unindex_owner(note: &Note) {
+    OWNER_INDEX.with_borrow_mut(|index| index.remove(&(note.owner, note.id)));
+}
+
+fn index_grants(note: &Note) {
+    GRANTEE_INDEX.with_borrow_mut(|index| {
+        for grantee in note
+            .shared_read
+            .iter()
+            .chain(note.shared_edit.iter())
+            .chain(note.shared_comment.iter())
+        {
+            index.insert((*grantee, note.id), ());
+        }
+    });
+    GROUP_NOTES.with_borrow_mut(|index| {
+        for group_id in note
+            .shared_read_groups
+            .iter()
+            .chain(note.shared_edit_groups.iter())
+            .chain(note.shared_comment_groups.iter())
+        {
+            index.insert((*group_id, note.id), ());
+        }
+    });
+    if let Some(workspace_id) = note.workspace_id {
+        WORKSPACE_NOTES.with_borrow_mut(|index| index.insert((workspace_id, note.id), ()));
+    }
+    if let Some(folder_id) = note.folder_id {
+        FOLDER_NOTES.with_borrow_mut(|index| index.insert((folder_id, note.id), ()));
+    }
+}
+
+/// Remove the owner and every grantee of `note` from the indexes
+pub fn unindex_note(note: &Note) {
+    OWNER_INDEX.with_borrow_mut(|index| index.remove(&(note.owner, note.id)));
+    GRANTEE_INDEX.with_borrow_mut(|index| {
+        for grantee in note
+            .shared_read
+            .iter()
+            .chain(note.shared_edit.iter())
+            .chain(note.shared_comment.iter())
+        {
+            index.remove(&(*grantee, note.id));
+        }
+    });
+    GROUP_NOTES.with_borrow_mut(|index| {
+        for group_id in note
+            .shared_read_groups
+            .iter()
+            .chain(note.shared_edit_groups.iter())
+            .chain(note.shared_comment_groups.iter())
+        {
+            index.remove(&(*group_id, note.id));
+        }
+    });
+    if let Some(workspace_id) = note.workspace_id {
+        WORKSPACE_NOTES.with_borrow_mut(|index| index.remove(&(workspace_id, note.id)));
+    }
+    if let Some(folder_id) = note.folder_id {
+        FOLDER_NOTES.with_borrow_mut(|index| index.remove(&(folder_id, note.id)));
+    }
+}
+
+/// Re-sync a single grantee entry after a share or unshare on `note`
+pub fn refresh_grantee(note: &Note, user: Principal) {
+    let granted = note.shared_read.contains(&user)
+        || note.shared_edit.contains(&user)
+        || note.shared_comment.contains(&user);
+
+    GRANTEE_INDEX.with_borrow_mut(|index| {
+        if granted {
+            index.insert((user, note.id), ());
+        } else {
+            index.remove(&(user, note.id));
+        }
+    });
+}
+
+/// Re-sync a single group entry after a group share or unshare on `note`
+pub fn refresh_group(note: &Note, group_id: GroupId) {
+    let granted = note.shared_read_groups.contains(&group_id)
+        || note.shared_edit_groups.contains(&group_id)
+        || note.shared_comment_groups.contains(&group_id);
+
+    GROUP_NOTES.with_borrow_mut(|index| {
+        if granted {
+            index.insert((group_id, note.id), ());
+        } else {
+            index.remove(&(group_id, note.id));
+        }
+    });
+}
+
+/// IDs of notes shared with `group_id`, ascending
+pub fn group_note_ids(group_id: GroupId) -> Vec<NoteId> {
+    GROUP_NOTES.with_borrow(|index| {
+        index
+            .keys_range((group_id, 0)..=(group_id, NoteId::MAX))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    })
+}
+
+/// IDs of notes owned by `workspace_id`, ascending
+pub fn workspace_note_ids(workspace_id: WorkspaceId) -> Vec<NoteId> {
+    WORKSPACE_NOTES.with_borrow(|index| {
+        index
+            .keys_range((workspace_id, 0)..=(workspace_id, NoteId::MAX))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    })
+}
+
+/// IDs of notes filed directly in `folder_id`, ascending
+pub fn folder_note_ids(folder_id: FolderId) -> Vec<NoteId> {
+    FOLDER_NOTES.with_borrow(|index| {
+        index
+            .keys_range((folder_id, 0)..=(folder_id, NoteId::MAX))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    })
+}
+
+/// IDs of notes owned by `owner`, ascending
+pub fn owned_note_ids(owner: Principal) -> Vec<NoteId> {
+    OWNER_INDEX.with_borrow(|index| {
+        index
+            .keys_range(principal_range(owner))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    })
+}
+
+/// IDs of notes where `grantee` holds a direct grant, ascending
+pub fn direct_grant_note_ids(grantee: Principal) -> Vec<NoteId> {
+    GRANTEE_INDEX.with_borrow(|index| {
+        index
+            .keys_range(principal_range(grantee))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    })
+}
+
+/// IDs of notes where `grantee` holds a grant directly, through a group,
+/// a workspace or a shared folder, ascending and deduplicated
+pub fn granted_note_ids(grantee: Principal) -> Vec<NoteId> {
+    let mut ids: Vec<NoteId> = GRANTEE_INDEX.with_borrow(|index| {
+        index
+            .keys_range(principal_range(grantee))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    });
+
+    let group_ids: Vec<GroupId> = GROUP_MEMBERS.with_borrow(|members| {
+        members
+            .keys_range((grantee, 0)..=(grantee, GroupId::MAX))
+            .map(|(_, group_id)| group_id)
+            .collect()
+    });
+    let workspace_ids: Vec<WorkspaceId> = WORKSPACE_MEMBERS.with_borrow(|members| {
+        members
+            .keys_range((grantee, 0)..=(grantee, WorkspaceId::MAX))
+            .map(|(_, workspace_id)| workspace_id)
+            .collect()
+    });
+    let folder_ids: Vec<FolderId> = FOLDER_GRANTEES.with_borrow(|grantees| {
+        grantees
+            .keys_range((grantee, 0)..=(grantee, FolderId::MAX))
+            .map(|(_, folder_id)| folder_id)
+            .collect()
+    });
+    if !group_ids.is_empty() || !workspace_ids.is_empty() || !folder_ids.is_empty() {
+        for group_id in group_ids {
+            ids.extend(group_note_ids(group_id));
+        }
+        for workspace_id in workspace_ids {
+            ids.extend(workspace_note_ids(workspace_id));
+        }
+        for folder_id in folder_ids {
+            for subfolder_id in subtree_folder_ids(folder_id) {
+                ids.extend(folder_note_ids(subfolder_id));
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+    }
+    ids
+}
+
+/// IDs of every note `principal` can read, ascending and deduplicated
+pub fn accessible_note_ids(principal: Principal) -> Vec<NoteId> {
+    let mut ids = owned_note_ids(principal);
+    ids.extend(granted_note_ids(principal));
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Populate the indexes from NOTES if they have never been built
+/// Called after upgrades so canisters that predate the indexes migrate
+/// Trashed notes are left out of the owner index only
+pub fn rebuild_note_indexes() {
+    let indexed = OWNER_INDEX.with_borrow(|index| !index.is_empty());
+    if indexed {
+        return;
+    }
+
+    NOTES.with_borrow(|store| {
+        for (_, note) in store.iter() {
+            if note.is_trashed() {
+                index_grants(&note);
+            } else {
+                index_note(&note);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note, principal};
+
+    #[test]
+    fn test_index_tracks_owner_and_grantees() {
+        let (alice, bob, carol) = (principal(1), principal(2), principal(3));
+        let mut shared = note(1, alice);
+        shared.shared_read.push(bob);
+        shared.shared_edit.push(carol);
+        index_note(&shared);
+        index_note(&note(2, bob));
+
+        assert_eq!(owned_note_ids(alice), vec![1]);
+        assert_eq!(granted_note_ids(bob), vec![1]);
+        assert_eq!(accessible_note_ids(bob), vec![1, 2]);
+        assert_eq!(accessible_note_ids(carol), vec![1]);
+
+        unindex_note(&shared);
+        assert!(owned_note_ids(alice).is_empty());
+        assert!(granted_note_ids(carol).is_empty());
+        assert_eq!(accessible_note_ids(bob), vec![2]);
+    }
+
+    #[test]
+    fn test_refresh_grantee_keeps_remaining_grant() {
+        let (alice, bob) = (principal(4), principal(5));
+        let mut shared = note(3, alice);
+        shared.shared_read.push(bob);
+        shared.shared_edit.push(bob);
+        index_note(&shared);
+
+        shared.shared_read.retain(|p| p != &bob);
+        refresh_grantee(&shared, bob);
+        assert_eq!(granted_note_ids(bob), vec![3]);
+
+        shared.shared_edit.retain(|p| p != &bob);
+        refresh_grantee(&shared, bob);
+        assert!(granted_note_ids(bob).is_empty());
+    }
+
+    #[test]
+    fn test_group_share_reaches_members() {
+        let (alice, bob) = (principal(6), principal(7));
+        GROUP_MEMBERS.with_borrow_mut(|members| members.insert((bob, 9), ()));
+        let mut shared = note(4, alice);
+        shared.shared_read_groups.push(9);
+        index_note(&shared);
+
+        assert_eq!(granted_note_ids(bob), vec![4]);
+
+        shared.shared_read_groups.clear();
+        refresh_group(&shared, 9);
+        assert!(granted_note_ids(bob).is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+// Note Invites Module
+// src/encrypted-notes-backend/src/invites.rs
+//
+// Lets an owner share a note with someone who has never logged in. Creating
+// an invite returns a random secret exactly once; the canister keeps only its
+// SHA-256 hash. The first registered user to redeem the secret before it
+// expires is granted the invite's permission on the note.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+use sha2::{Digest, Sha256};
+
+use crate::audit::record_access;
+use crate::changes::record_note_change;
+use crate::helpers::{assert_not_anonymous, get_next_id};
+use crate::note::apply_grant;
+use crate::storage::{INVITE_EXPIRY_QUEUE, INVITE_TOKENS, NOTES, NOTE_INVITES, USER_PROFILES};
+use crate::types::{AuditAction, ChangeKind, InviteId, NoteId, NoteInvite, SharePermission};
+
+/// Longest allowed invite lifetime (30 days)
+const MAX_INVITE_TTL_NANOS: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+/// Most invites forgotten by a single run of the cleanup job
+const MAX_FORGOTTEN_PER_RUN: usize = 500;
+
+/// Create a single-use invite to a note, valid until `expires_at` (nanoseconds since epoch)
+/// Only the owner can invite; returns the secret, which cannot be retrieved again
+#[update]
+pub async fn create_note_invite(
+    note_id: NoteId,
+    permission: SharePermission,
+    expires_at: u64,
+) -> String {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    assert_can_invite(note_id, &caller);
+
+    let now = ic_cdk::api::time();
+    if expires_at <= now {
+        ic_cdk::trap("Invite expiry must be in the future");
+    }
+    if expires_at - now > MAX_INVITE_TTL_NANOS {
+        ic_cdk::trap("Invites can be valid for at most 30 days");
+    }
+
+    let random = ic_cdk::management_canister::raw_rand()
+        .await
+        .unwrap_or_else(|_| ic_cdk::trap("Failed to generate invite secret"));
+    let secret = hex::encode(random);
+
+    // The note may have changed hands while waiting for randomness
+    assert_can_invite(note_id, &caller);
+
+    let token_hash = hash_secret(&secret);
+    let invite_id = get_next_id();
+    let invite = NoteInvite {
+        id: invite_id,
+        note_id,
+        created_by: caller,
+        permission,
+        token_hash: token_hash.to_vec(),
+        created_at: ic_cdk::api::time(),
+        expires_at,
+        redeemed_by: None,
+        redeemed_at: None,
+        revoked_at: None,
+    };
+
+    INVITE_TOKENS.with_borrow_mut(|tokens| tokens.insert(token_hash, (note_id, invite_id)));
+    INVITE_EXPIRY_QUEUE.with_borrow_mut(|queue| queue.insert((expires_at, note_id, invite_id), ()));
+    NOTE_INVITES.with_borrow_mut(|invites| invites.insert((note_id, invite_id), invite));
+
+    secret
+}
+
+/// Redeem an invite secret, gaining its permission on the note
+/// The caller must have registered with `register_user`; returns the note ID
+#[update]
+pub fn redeem_note_invite(secret: String) -> NoteId {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    if !USER_PROFILES.with_borrow(|profiles| profiles.contains_key(&caller)) {
+        ic_cdk::trap("Register before redeeming an invite");
+    }
+
+    let token_hash = hash_secret(secret.trim());
+    let (note_id, invite_id) = INVITE_TOKENS
+        .with_borrow(|tokens| tokens.get(&token_hash))
+        .unwrap_or_else(|| ic_cdk::trap("Invalid or already used invite"));
+    let mut invite = NOTE_INVITES
+        .with_borrow(|invites| invites.get(&(note_id, invite_id)))
+        .unwrap_or_else(|| ic_cdk::trap("Invalid or already used invite"));
+
+    let now = ic_cdk::api::time();
+    if !invite.is_outstanding(now) {
+        ic_cdk::trap("Invite has expired");
+    }
+
+    NOTES.with_borrow_mut(|store| {
+        let mut note = store
+            .get(&note_id)
+            .unwrap_or_else(|| ic_cdk::trap("Note not found"));
+        if note.is_trashed() {
+            ic_cdk::trap("Note is in the trash");
+        }
+        if !note.is_managed_by(&invite.created_by) {
+            ic_cdk::trap("Invite is no longer valid");
+        }
+
+        if note.owner != caller {
+            apply_grant(&mut note, caller, invite.permission, None);
+            record_note_change(None, &note, ChangeKind::AccessChanged);
+        }
+        record_access(
+            note_id,
+            caller,
+            AuditAction::InviteRedeemed {
+                invite_id,
+                permission: invite.permission,
+            },
+        );
+        store.insert(note_id, note);
+    });
+
+    forget_token(&invite);
+    invite.redeemed_by = Some(caller);
+    invite.redeemed_at = Some(now);
+    NOTE_INVITES.with_borrow_mut(|invites| invites.insert((note_id, invite_id), invite));
+
+    note_id
+}
+
+/// List a note's invites, including redeemed, revoked and expired ones
+/// Only the owner can list invites
+#[query]
+pub fn list_note_invites(note_id: NoteId) -> Vec<NoteInvite> {
+    let caller = msg_caller();
+    assert_can_invite(note_id, &caller);
+
+    NOTE_INVITES.with_borrow(|invites| {
+        invites
+            .range(invite_range(note_id))
+            .map(|(_, invite)| invite)
+            .collect()
+    })
+}
+
+/// Revoke an outstanding invite so its secret can no longer be redeemed
+/// Only the owner can revoke invites
+#[update]
+pub fn revoke_note_invite(note_id: NoteId, invite_id: InviteId) {
+    let caller = msg_caller();
+    assert_can_invite(note_id, &caller);
+
+    let mut invite = NOTE_INVITES
+        .with_borrow(|invites| invites.get(&(note_id, invite_id)))
+        .unwrap_or_else(|| ic_cdk::trap("Invite not found"));
+    if invite.redeemed_at.is_some() {
+        ic_cdk::trap("Invite has already been redeemed");
+    }
+    if invite.revoked_at.is_some() {
+        return;
+    }
+
+    forget_token(&invite);
+    invite.revoked_at = Some(ic_cdk::api::time());
+    NOTE_INVITES.with_borrow_mut(|invites| invites.insert((note_id, invite_id), invite));
+}
+
+/// Drop the secrets of the earliest invites that expired without being
+/// redeemed, at most `MAX_FORGOTTEN_PER_RUN` per call
+/// Runs periodically from the timer registered in timers.rs
+pub fn forget_expired_invites() {
+    let now = ic_cdk::api::time();
+
+    let expired: Vec<(u64, NoteId, InviteId)> = INVITE_EXPIRY_QUEUE.with_borrow(|queue| {
+        queue
+            .keys()
+            .take_while(|(expires_at, _, _)| *expires_at <= now)
+            .take(MAX_FORGOTTEN_PER_RUN)
+            .collect()
+    });
+
+    for key @ (_, note_id, invite_id) in expired {
+        INVITE_EXPIRY_QUEUE.with_borrow_mut(|queue| queue.remove(&key));
+        if let Some(invite) = NOTE_INVITES.with_borrow(|invites| invites.get(&(note_id, invite_id)))
+        {
+            forget_token(&invite);
+        }
+    }
+}
+
+/// Queue every invite whose secret is still live if the queue has never been built
+/// Called after upgrades so canisters that predate the queue migrate
+pub fn rebuild_invite_expiry_queue() {
+    let queued = INVITE_EXPIRY_QUEUE.with_borrow(|queue| !queue.is_empty());
+    if queued {
+        return;
+    }
+
+    INVITE_TOKENS.with_borrow(|tokens| {
+        NOTE_INVITES.with_borrow(|invites| {
+            INVITE_EXPIRY_QUEUE.with_borrow_mut(|queue| {
+                for (_, (note_id, invite_id)) in tokens.iter() {
+                    if let Some(invite) = invites.get(&(note_id, invite_id)) {
+                        queue.insert((invite.expires_at, note_id, invite_id), ());
+                    }
+                }
+            })
+        })
+    });
+}
+
+/// Remove every invite of a note
+pub fn clear_invites(note_id: NoteId) {
+    let invites: Vec<NoteInvite> = NOTE_INVITES.with_borrow(|invites| {
+        invites
+            .range(invite_range(note_id))
+            .map(|(_, invite)| invite)
+            .collect()
+    });
+
+    for invite in invites {
+        forget_token(&invite);
+        NOTE_INVITES.with_borrow_mut(|store| store.remove(&(note_id, invite.id)));
+    }
+}
+
+fn invite_range(note_id: NoteId) -> std::ops::RangeInclusive<(NoteId, InviteId)> {
+    (note_id, 0)..=(note_id, InviteId::MAX)
+}
+
+fn hash_secret(secret: &str) -> [u8; 32] {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+fn forget_token(invite: &NoteInvite) {
+    if let Ok(token_hash) = <[u8; 32]>::try_from(invite.token_hash.as_slice()) {
+        INVITE_TOKENS.with_borrow_mut(|tokens| tokens.remove(&token_hash));
+    }
+    INVITE_EXPIRY_QUEUE
+        .with_borrow_mut(|queue| queue.remove(&(invite.expires_at, invite.note_id, invite.id)));
+}
+
+fn assert_can_invite(note_id: NoteId, caller: &Principal) {
+    match NOTES.with_borrow(|store| store.get(&note_id)) {
+        Some(note) if note.is_trashed() => ic_cdk::trap("Note is in the trash"),
+        Some(note) if note.is_managed_by(caller) => {}
+        Some(_) => ic_cdk::trap("Only owner can manage invites"),
+        None => ic_cdk::trap("Note not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::principal;
+
+    fn invite(note_id: NoteId, id: InviteId, secret: &str) -> NoteInvite {
+        NoteInvite {
+            id,
+            note_id,
+            created_by: principal(1),
+            permission: SharePermission::Read,
+            token_hash: hash_secret(secret).to_vec(),
+            created_at: 0,
+            expires_at: 100,
+            redeemed_by: None,
+            redeemed_at: None,
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn test_invite_is_outstanding_until_used_or_expired() {
+        let mut pending = invite(1, 1, "secret");
+        assert!(pending.is_outstanding(99));
+        assert!(!pending.is_outstanding(100));
+
+        pending.revoked_at = Some(50);
+        assert!(!pending.is_outstanding(60));
+    }
+
+    #[test]
+    fn test_clear_invites_forgets_secrets() {
+        for (id, secret) in [(1, "first"), (2, "second")] {
+            let pending = invite(7, id, secret);
+            INVITE_TOKENS.with_borrow_mut(|tokens| tokens.insert(hash_secret(secret), (7, id)));
+            INVITE_EXPIRY_QUEUE.with_borrow_mut(|queue| queue.insert((100, 7, id), ()));
+            NOTE_INVITES.with_borrow_mut(|invites| invites.insert((7, id), pending));
+        }
+
+        clear_invites(7);
+
+        assert!(INVITE_TOKENS.with_borrow(|tokens| !tokens.contains_key(&hash_secret("first"))));
+        assert!(INVITE_TOKENS.with_borrow(|tokens| !tokens.contains_key(&hash_secret("second"))));
+        assert!(NOTE_INVITES.with_borrow(|invites| invites.range(invite_range(7)).next().is_none()));
+        assert!(INVITE_EXPIRY_QUEUE.with_borrow(|queue| queue.is_empty()));
+    }
+}
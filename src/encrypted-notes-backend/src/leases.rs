@@ -0,0 +1,197 @@
+// Edit Lease Module
+// src/encrypted-notes-backend/src/leases.rs
+//
+// Pessimistic locking for teams that prefer taking turns over merging. An
+// editor acquires a short lease on a note and keeps it alive with heartbeats;
+// while it is held, content writes from anyone else are rejected with the
+// holder's identity. A lease lapses when its heartbeats stop or when its
+// holder loses edit access, and lapsed leases are cleared periodically. The
+// owner can always break a lease.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::helpers::assert_not_anonymous;
+use crate::storage::{EDIT_LEASES, NOTES};
+use crate::types::{EditLease, Note, NoteId};
+
+/// How long a lease lasts without a heartbeat (60 seconds)
+const LEASE_DURATION_NANOS: u64 = 60 * 1_000_000_000;
+
+/// Acquire the edit lease of a note, or renew it if the caller already holds it
+/// Requires edit permissions; traps with the holder if someone else holds it
+#[update]
+pub fn acquire_edit_lease(note_id: NoteId) -> EditLease {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let note = editable_note(note_id, &caller);
+    assert_not_leased(&note, &caller);
+
+    let now = ic_cdk::api::time();
+    let lease = match EDIT_LEASES.with_borrow(|leases| leases.get(&note_id)) {
+        Some(lease) if lease.holder == caller && lease.expires_at > now => EditLease {
+            expires_at: now + LEASE_DURATION_NANOS,
+            ..lease
+        },
+        _ => EditLease {
+            note_id,
+            holder: caller,
+            acquired_at: now,
+            expires_at: now + LEASE_DURATION_NANOS,
+        },
+    };
+    EDIT_LEASES.with_borrow_mut(|leases| leases.insert(note_id, lease.clone()));
+    lease
+}
+
+/// Heartbeat: extend the caller's lease on a note
+/// Traps if the caller's lease has already lapsed, so the client knows to reload
+#[update]
+pub fn renew_edit_lease(note_id: NoteId) -> EditLease {
+    let caller = msg_caller();
+
+    let note = editable_note(note_id, &caller);
+    let now = ic_cdk::api::time();
+    let mut lease = match active_lease(&note, now) {
+        Some(lease) if lease.holder == caller => lease,
+        _ => ic_cdk::trap("You do not hold the edit lease of this note"),
+    };
+
+    lease.expires_at = now + LEASE_DURATION_NANOS;
+    EDIT_LEASES.with_borrow_mut(|leases| leases.insert(note_id, lease.clone()));
+    lease
+}
+
+/// Release the caller's lease on a note
+#[update]
+pub fn release_edit_lease(note_id: NoteId) {
+    let caller = msg_caller();
+
+    EDIT_LEASES.with_borrow_mut(|leases| {
+        if matches!(leases.get(&note_id), Some(lease) if lease.holder == caller) {
+            leases.remove(&note_id);
+        }
+    });
+}
+
+/// Break the lease on a note regardless of who holds it
+/// Only the owner can break leases
+#[update]
+pub fn break_edit_lease(note_id: NoteId) {
+    let caller = msg_caller();
+
+    match NOTES.with_borrow(|store| store.get(&note_id)) {
+        Some(note) if note.is_managed_by(&caller) => {}
+        Some(_) => ic_cdk::trap("Only the owner can break an edit lease"),
+        None => ic_cdk::trap("Note not found"),
+    }
+    EDIT_LEASES.with_borrow_mut(|leases| leases.remove(&note_id));
+}
+
+/// Get the lease currently held on a note, if any
+/// Requires read permissions
+#[query]
+pub fn get_edit_lease(note_id: NoteId) -> Option<EditLease> {
+    let caller = msg_caller();
+
+    let note = match NOTES.with_borrow(|store| store.get(&note_id)) {
+        Some(note) if note.can_read(&caller) => note,
+        Some(_) => ic_cdk::trap("Not authorized to read this note"),
+        None => ic_cdk::trap("Note not found"),
+    };
+    active_lease(&note, ic_cdk::api::time())
+}
+
+/// The lease held on `note` by someone other than `caller`, if any
+pub fn foreign_lease(note: &Note, caller: &Principal) -> Option<EditLease> {
+    active_lease(note, ic_cdk::api::time()).filter(|lease| &lease.holder != caller)
+}
+
+/// Trap with the holder's identity if someone other than `caller` holds the lease on `note`
+pub fn assert_not_leased(note: &Note, caller: &Principal) {
+    if let Some(lease) = foreign_lease(note, caller) {
+        ic_cdk::trap(format!(
+            "Note is being edited by {} until {}",
+            lease.holder, lease.expires_at
+        ));
+    }
+}
+
+/// Drop leases that expired or whose holder can no longer edit the note
+/// Runs periodically from the timer registered in timers.rs
+pub fn clear_expired_leases() {
+    let now = ic_cdk::api::time();
+
+    let lapsed: Vec<NoteId> = EDIT_LEASES.with_borrow(|leases| {
+        NOTES.with_borrow(|store| {
+            leases
+                .iter()
+                .filter(|(note_id, lease)| {
+                    store
+                        .get(note_id)
+                        .is_none_or(|note| !is_active(lease, &note, now))
+                })
+                .map(|(note_id, _)| note_id)
+                .collect()
+        })
+    });
+
+    EDIT_LEASES.with_borrow_mut(|leases| {
+        for note_id in lapsed {
+            leases.remove(&note_id);
+        }
+    });
+}
+
+/// Remove the lease of a note, if any
+pub fn clear_edit_lease(note_id: NoteId) {
+    EDIT_LEASES.with_borrow_mut(|leases| leases.remove(&note_id));
+}
+
+fn editable_note(note_id: NoteId, caller: &Principal) -> Note {
+    match NOTES.with_borrow(|store| store.get(&note_id)) {
+        Some(note) if note.can_edit(caller) => note,
+        Some(_) => ic_cdk::trap("Not authorized to edit this note"),
+        None => ic_cdk::trap("Note not found"),
+    }
+}
+
+fn active_lease(note: &Note, now: u64) -> Option<EditLease> {
+    EDIT_LEASES
+        .with_borrow(|leases| leases.get(&note.id))
+        .filter(|lease| is_active(lease, note, now))
+}
+
+fn is_active(lease: &EditLease, note: &Note, now: u64) -> bool {
+    lease.expires_at > now && note.can_edit(&lease.holder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note, principal};
+
+    #[test]
+    fn test_lease_lapses_on_expiry_or_lost_access() {
+        let (alice, bob) = (principal(1), principal(2));
+        let mut note = Note {
+            shared_edit: vec![bob],
+            ..note(1, alice)
+        };
+        let lease = EditLease {
+            note_id: 1,
+            holder: bob,
+            acquired_at: 0,
+            expires_at: 100,
+        };
+
+        assert!(is_active(&lease, &note, 99));
+        assert!(!is_active(&lease, &note, 100));
+
+        note.shared_edit.clear();
+        note.shared_read.push(bob);
+        assert!(!is_active(&lease, &note, 99));
+    }
+}
@@ -1,18 +1,47 @@
+mod account;
 mod ai;
 mod ai_endpoints;
 mod ai_service_new;
+mod archive;
+mod attachments;
+mod audit;
+mod changes;
+mod chunks;
+mod comments;
+mod folders;
+mod groups;
 mod helpers;
+mod inbox;
+mod index;
+mod invites;
+mod leases;
+mod metadata;
 mod nft;
 mod note;
+mod oplog;
 mod search;
 mod storage;
+#[cfg(test)]
+mod test_utils;
+mod timers;
+mod transfer;
+mod trash;
 mod types;
 mod user;
+mod workspaces;
 
 use candid::Principal;
 use ic_cdk::{api::msg_caller, query};
 use ic_cdk::export_candid;
-use types::{Nft, NftId, Note, NoteId, UserProfile};
+use types::{
+    AccountDeletionReceipt, AccountExportPage, Attachment, AttachmentId, AttachmentInfo,
+    AuditEntry, ChangeFeed, Comment, CommentId, EditLease, Folder, FolderId, Group, GroupId,
+    InviteId, NewNoteOp, Nft, NftId, Note, NoteId, NoteIdMapping, NoteInvite, NoteMetadata,
+    NoteOpBatch, NoteOrder, NoteRevision, NoteRevisionSummary, NoteSummary, NoteTransfer,
+    NoteUpdateError, Page, PageRequest, PendingShare, RevisionPolicy, ShareError, ShareGrant,
+    SharePermission, UploadId, UserProfile, UsernameShareResult, Workspace, WorkspaceId,
+    WorkspaceRole,
+};
 
 // AI types for export_candid
 use crate::ai::{
@@ -42,6 +71,20 @@ pub fn set_max_note_size(new_size: usize) -> Result<(), String> {
     crate::helpers::set_max_note_size(new_size)
 }
 
+#[ic_cdk::init]
+fn init() {
+    timers::start_timers();
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    index::rebuild_note_indexes();
+    trash::rebuild_trash_queue();
+    invites::rebuild_invite_expiry_queue();
+    inbox::rebuild_offer_index();
+    timers::start_timers();
+}
+
 #[query]
 fn whoami() -> Principal {
     msg_caller()
@@ -49,15 +92,105 @@ fn whoami() -> Principal {
 
 // User Management Endpoints - Re-exported from user module
 pub use user::{
-    get_my_profile, get_other_users, get_profile, get_registered_users, get_user_count,
-    is_user_registered, register_user, search_users_by_username, update_profile,
+    get_my_profile, get_other_users, get_profile, get_registered_users,
+    get_registered_users_page, get_user_count, is_user_registered, register_user,
+    search_users_by_username, update_profile,
+};
+
+// Comment Endpoints - Re-exported from comments module
+pub use comments::{add_comment, delete_comment, edit_comment, list_comments, set_comment_resolved};
+
+// Folder Endpoints - Re-exported from folders module
+pub use folders::{
+    create_folder, delete_folder, encrypted_symmetric_key_for_folder, get_folder, get_my_folders,
+    get_shared_folders, list_folder_notes, list_subfolders, move_folder, move_note_to_folder,
+    rename_folder, share_folder, unshare_folder,
+};
+
+// Sharing Group Endpoints - Re-exported from groups module
+pub use groups::{
+    add_group_members, create_group, delete_group, get_group, get_group_memberships,
+    get_my_groups, remove_group_members, rename_group, share_note_with_group,
+    unshare_note_from_group,
+};
+
+// Share Inbox Endpoints - Re-exported from inbox module
+pub use inbox::{
+    accept_share, block_user, decline_share, get_blocked_users, get_share_inbox, unblock_user,
+};
+
+// Invite Endpoints - Re-exported from invites module
+pub use invites::{create_note_invite, list_note_invites, redeem_note_invite, revoke_note_invite};
+
+// Edit Lease Endpoints - Re-exported from leases module
+pub use leases::{
+    acquire_edit_lease, break_edit_lease, get_edit_lease, release_edit_lease, renew_edit_lease,
+};
+
+// Note Metadata Endpoints - Re-exported from metadata module
+pub use metadata::{
+    get_my_notes_metadata_page, get_note_metadata, get_shared_notes_metadata_page,
+    read_notes_metadata_page, update_note_metadata,
 };
 
 // Note Management Endpoints - Re-exported from note module
 pub use note::{
-    create_note, delete_note, encrypted_symmetric_key_for_note, get_my_notes, get_note,
-    get_note_count, get_shared_notes, read_notes, share_note_edit, share_note_read,
-    symmetric_key_verification_key_for_note, unshare_note_edit, unshare_note_read, update_note,
+    create_note, delete_note, encrypted_symmetric_key_for_note,
+    encrypted_symmetric_key_for_note_epoch, get_my_notes, get_my_notes_page,
+    get_my_notes_sorted, get_note, get_note_count, get_note_key_epoch, get_note_revision,
+    get_revision_policy, get_shared_notes, get_shared_notes_page, get_shared_notes_sorted,
+    list_note_revisions, list_note_shares, read_notes, read_notes_page, read_notes_sorted,
+    restore_note_revision, set_revision_policy, share_note_by_username, share_note_comment,
+    share_note_edit, share_note_read, share_note_with_expiry, share_note_with_usernames,
+    symmetric_key_verification_key_for_note, unshare_note_comment, unshare_note_edit,
+    unshare_note_read, update_note, update_note_versioned,
+};
+
+// Operation Log Endpoints - Re-exported from oplog module
+pub use oplog::{
+    append_note_ops, compact_note_ops, disable_note_op_log, enable_note_op_log, get_note_ops,
+};
+
+// Account Deletion Endpoints - Re-exported from account module
+pub use account::{cancel_account_deletion, confirm_account_deletion, request_account_deletion};
+
+// Account Archive Endpoints - Re-exported from archive module
+pub use archive::{
+    begin_account_import, export_account_page, finish_account_import, import_account_page,
+};
+
+// Attachment Endpoints - Re-exported from attachments module
+pub use attachments::{add_attachment, delete_attachment, get_attachment, list_attachments};
+
+// Audit Log Endpoints - Re-exported from audit module
+pub use audit::{get_audit_log_cap, get_note_audit_log, set_audit_log_cap};
+
+// Changefeed Endpoints - Re-exported from changes module
+pub use changes::{get_change_cursor, get_changes};
+
+// Chunked Upload Endpoints - Re-exported from chunks module
+pub use chunks::{
+    abort_note_upload, begin_note_upload, commit_note_upload, get_note_chunk, upload_note_chunk,
+};
+
+// Ownership Transfer Endpoints - Re-exported from transfer module
+pub use transfer::{
+    accept_note_transfer, cancel_note_transfer, encrypted_symmetric_key_for_transfer,
+    get_incoming_transfers, get_note_transfers, offer_note_transfer, stage_transfer_attachment,
+    stage_transfer_content,
+};
+
+// Trash Bin Endpoints - Re-exported from trash module
+pub use trash::{
+    empty_trash, get_trash_retention_nanos, list_trash, purge_note, restore_note,
+    set_trash_retention_nanos,
+};
+
+// Workspace Endpoints - Re-exported from workspaces module
+pub use workspaces::{
+    create_workspace, create_workspace_note, get_my_workspaces, get_workspace,
+    get_workspace_notes, reassign_workspace_note, remove_workspace_member, rename_workspace,
+    set_workspace_member,
 };
 
 // Search Index Management Endpoints - Re-exported from search module
@@ -79,8 +212,8 @@ pub use ai_endpoints::{
 
 // NFT Management Endpoints - Re-exported from nft module
 pub use nft::{
-    buy_nft, get_nft, list_my_nfts, list_nfts_for_sale, mint_note_to_nft, owner_of, tokens_of,
-    transfer_nft, update_listing,
+    buy_nft, get_nft, list_my_nfts, list_my_nfts_page, list_nfts_for_sale,
+    list_nfts_for_sale_page, mint_note_to_nft, owner_of, tokens_of, transfer_nft, update_listing,
 };
 
 export_candid!();
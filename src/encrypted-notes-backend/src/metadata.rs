@@ -0,0 +1,170 @@
+// Note Metadata Module
+// src/encrypted-notes-backend/src/metadata.rs
+//
+// Each note can carry a small client-encrypted sidecar (title, snippet, tags,
+// color) stored apart from its content, so list views can be rendered
+// without downloading and decrypting every note. The sidecar is encrypted
+// under the note's vetKD key and guarded by the note's own permissions.
+
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::changes::record_note_change;
+use crate::helpers::assert_not_anonymous;
+use crate::index::{accessible_note_ids, granted_note_ids, owned_note_ids};
+use crate::note::{assert_latest_key_epoch, notes_page};
+use crate::storage::{NOTES, NOTE_METADATA};
+use crate::types::{ChangeKind, Note, NoteId, NoteMetadata, NoteSummary, Page, PageRequest};
+
+/// Maximum size of an encrypted metadata sidecar, kept small on purpose
+pub const MAX_METADATA_SIZE: usize = 4096;
+
+/// Replace the metadata sidecar of a note
+/// Requires edit permissions; the sidecar must be encrypted under the note's
+/// latest key epoch
+#[update]
+pub fn update_note_metadata(note_id: NoteId, encrypted: String, key_epoch: u64) {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    if encrypted.len() > MAX_METADATA_SIZE {
+        ic_cdk::trap(format!(
+            "Metadata too large: {} bytes exceeds limit of {} bytes",
+            encrypted.len(),
+            MAX_METADATA_SIZE
+        ));
+    }
+
+    let note = NOTES.with_borrow(|store| match store.get(&note_id) {
+        Some(note) if note.can_edit(&caller) => note,
+        Some(_) => ic_cdk::trap("Not authorized to edit this note"),
+        None => ic_cdk::trap("Note not found"),
+    });
+    assert_latest_key_epoch(&note, Some(key_epoch));
+
+    let metadata = NoteMetadata {
+        encrypted,
+        key_epoch,
+        updated_at: ic_cdk::api::time(),
+        updated_by: caller,
+    };
+    NOTE_METADATA.with_borrow_mut(|sidecars| sidecars.insert(note_id, metadata));
+    record_note_change(None, &note, ChangeKind::Updated);
+}
+
+/// Get the metadata sidecar of a note, if it has one
+/// Requires read permissions
+#[query]
+pub fn get_note_metadata(note_id: NoteId) -> Option<NoteMetadata> {
+    let caller = msg_caller();
+
+    NOTES.with_borrow(|store| match store.get(&note_id) {
+        Some(note) if note.can_read(&caller) => {}
+        Some(_) => ic_cdk::trap("Not authorized to read this note"),
+        None => ic_cdk::trap("Note not found"),
+    });
+    NOTE_METADATA.with_borrow(|sidecars| sidecars.get(&note_id))
+}
+
+/// List one page of the caller's accessible notes without their content, ordered by note ID
+#[query]
+pub fn read_notes_metadata_page(request: PageRequest) -> Page<NoteSummary> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    summaries(notes_page(accessible_note_ids(caller), &request, |note| {
+        note.can_read(&caller)
+    }))
+}
+
+/// List one page of the caller's own notes without their content, ordered by note ID
+#[query]
+pub fn get_my_notes_metadata_page(request: PageRequest) -> Page<NoteSummary> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    summaries(notes_page(owned_note_ids(caller), &request, |_| true))
+}
+
+/// List one page of notes shared with the caller without their content, ordered by note ID
+#[query]
+pub fn get_shared_notes_metadata_page(request: PageRequest) -> Page<NoteSummary> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    summaries(notes_page(granted_note_ids(caller), &request, |note| {
+        note.owner != caller && note.can_read(&caller)
+    }))
+}
+
+/// Remove the metadata sidecar of a note
+pub fn clear_note_metadata(note_id: NoteId) {
+    NOTE_METADATA.with_borrow_mut(|sidecars| sidecars.remove(&note_id));
+}
+
+fn summaries(page: Page<Note>) -> Page<NoteSummary> {
+    NOTE_METADATA.with_borrow(|sidecars| Page {
+        items: page
+            .items
+            .into_iter()
+            .map(|note| NoteSummary {
+                id: note.id,
+                owner: note.owner,
+                version: note.version,
+                created_at: note.created_at,
+                updated_at: note.updated_at,
+                key_epoch: note.key_epoch,
+                folder_id: note.folder_id,
+                workspace_id: note.workspace_id,
+                metadata: sidecars.get(&note.id),
+            })
+            .collect(),
+        next_cursor: page.next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note, principal};
+
+    #[test]
+    fn test_summaries_attach_sidecars() {
+        let owner = principal(1);
+        let stored = |id| Note {
+            encrypted: "large ciphertext".to_string(),
+            version: 2,
+            key_epoch: 1,
+            content_key_epoch: 1,
+            ..note(id, owner)
+        };
+        NOTE_METADATA.with_borrow_mut(|sidecars| {
+            sidecars.insert(
+                1,
+                NoteMetadata {
+                    encrypted: "title".to_string(),
+                    key_epoch: 1,
+                    updated_at: 0,
+                    updated_by: owner,
+                },
+            )
+        });
+
+        let page = summaries(Page {
+            items: vec![stored(1), stored(2)],
+            next_cursor: Some("2".to_string()),
+        });
+
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(page.items[0].version, 2);
+        assert_eq!(
+            page.items[0]
+                .metadata
+                .as_ref()
+                .map(|m| m.encrypted.as_str()),
+            Some("title")
+        );
+        assert!(page.items[1].metadata.is_none());
+        assert_eq!(page.next_cursor, Some("2".to_string()));
+    }
+}
@@ -2,13 +2,23 @@ use candid::{CandidType, Nat, Principal};
 use ic_cdk::api::{canister_self, msg_caller};
 use ic_cdk::{call, query, update};
 use serde::Deserialize;
+use std::ops::Bound;
 
+use crate::attachments::clear_attachments;
+use crate::audit::clear_audit_log;
+use crate::changes::record_note_change;
+use crate::comments::clear_comments;
 use crate::helpers::{
-    assert_not_anonymous, btc_to_stats, get_max_note_size, get_next_id,
-    nns_canister_self_pointer_to_note,
+    assert_not_anonymous, btc_to_stats, collect_pages, get_max_note_size, get_next_id,
+    nns_canister_self_pointer_to_note, paginate, parse_cursor,
 };
+use crate::index::{index_note, unindex_note};
+use crate::invites::clear_invites;
+use crate::leases::clear_edit_lease;
+use crate::metadata::clear_note_metadata;
+use crate::note::clear_revisions;
 use crate::storage::{get_ledger_ids, NFTS, NOTES};
-use crate::types::{Account, Nft, NftId, NoteId};
+use crate::types::{Account, ChangeKind, Nft, NftId, NoteId, Page, PageRequest};
 
 const ADMIN_FEE_PERCENT: u64 = 3;
 
@@ -41,6 +51,12 @@ pub fn mint_note_to_nft(
             if note.owner != caller {
                 ic_cdk::trap("Only the owner can mint this note to NFT");
             }
+            if note.is_trashed() {
+                ic_cdk::trap("Cannot mint a note that is in the trash");
+            }
+            if note.workspace_id.is_some() {
+                ic_cdk::trap("Workspace notes cannot be minted to NFTs");
+            }
             let max_size = get_max_note_size();
             if note.encrypted.len() > max_size {
                 ic_cdk::trap(&format!(
@@ -93,29 +109,45 @@ pub fn get_nft(nft_id: NftId) -> Option<Nft> {
 }
 
 /// List all NFTs owned by the caller
+/// Unpaged wrapper around `list_my_nfts_page`, kept until clients migrate
 #[query]
 pub fn list_my_nfts() -> Vec<Nft> {
+    collect_pages(list_my_nfts_page)
+}
+
+/// List one page of NFTs owned by the caller, ordered by NFT ID
+#[query]
+pub fn list_my_nfts_page(request: PageRequest) -> Page<Nft> {
     let caller = msg_caller();
     let _ = assert_not_anonymous(&caller);
 
-    NFTS.with_borrow(|store| {
-        store
-            .iter()
-            .filter(|(_, nft)| nft.owner == caller)
-            .map(|(_, nft)| nft.clone())
-            .collect()
-    })
+    nfts_page(&request, |nft| nft.owner == caller)
 }
 
 /// List all NFTs currently up for sale
+/// Unpaged wrapper around `list_nfts_for_sale_page`, kept until clients migrate
 #[query]
 pub fn list_nfts_for_sale() -> Vec<Nft> {
+    collect_pages(list_nfts_for_sale_page)
+}
+
+/// List one page of NFTs currently up for sale, ordered by NFT ID
+#[query]
+pub fn list_nfts_for_sale_page(request: PageRequest) -> Page<Nft> {
+    nfts_page(&request, |nft| nft.listed)
+}
+
+fn nfts_page(request: &PageRequest, keep: impl Fn(&Nft) -> bool) -> Page<Nft> {
+    let start = match parse_cursor::<NftId>(request) {
+        Some(cursor) => Bound::Excluded(cursor),
+        None => Bound::Unbounded,
+    };
+
     NFTS.with_borrow(|store| {
-        store
-            .iter()
-            .filter(|(_, nft)| nft.listed)
-            .map(|(_, nft)| nft.clone())
-            .collect()
+        let entries = store
+            .range((start, Bound::Unbounded))
+            .filter(|(_, nft)| keep(nft));
+        paginate(entries, request)
     })
 }
 
@@ -323,12 +355,33 @@ pub async fn buy_nft(nft_id: NftId) -> Result<String, String> {
 
             NOTES.with_borrow_mut(|notes| {
                 if let Some(mut note) = notes.get(&nft.note_id) {
+                    let before = note.clone();
+                    unindex_note(&note);
                     note.owner = buyer;
                     note.shared_read.clear();
                     note.shared_edit.clear();
+                    note.share_expirations.clear();
+                    note.shared_read_groups.clear();
+                    note.shared_edit_groups.clear();
+                    note.shared_comment.clear();
+                    note.shared_comment_groups.clear();
+                    note.folder_id = None;
+                    index_note(&note);
+                    record_note_change(Some(&before), &note, ChangeKind::OwnerChanged);
                     notes.insert(nft.note_id, note);
                 }
             });
+            // The seller's edit history, comment threads, metadata sidecar and
+            // attachments are encrypted under the seller's key, and nobody is
+            // around to re-encrypt them for the buyer, so they do not transfer.
+            // Nor do the seller's audit log, outstanding invites and edit lease.
+            clear_revisions(nft.note_id);
+            clear_comments(nft.note_id);
+            clear_note_metadata(nft.note_id);
+            clear_attachments(nft.note_id);
+            clear_audit_log(nft.note_id);
+            clear_invites(nft.note_id);
+            clear_edit_lease(nft.note_id);
 
             nft.owner = buyer;
             nft.listed = false;
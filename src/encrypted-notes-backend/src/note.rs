@@ -9,10 +9,36 @@ use ic_cdk::management_canister::{
 };
 use ic_cdk::{query, update};
 use ic_stable_structures::Storable;
+use std::cmp::Reverse;
 
-use crate::helpers::{assert_not_anonymous, get_next_id, get_max_note_size};
-use crate::storage::{NOTES, NFTS};
-use crate::types::{Note, NoteId};
+use crate::audit::record_access;
+use crate::changes::record_note_change;
+use crate::chunks::{assert_not_chunked, clear_note_chunks};
+use crate::helpers::{
+    assert_not_anonymous, collect_pages, get_next_id, get_max_note_size, paginate, parse_cursor,
+};
+use crate::inbox::{offer_share, withdraw_offer};
+use crate::index::{
+    accessible_note_ids, direct_grant_note_ids, granted_note_ids, index_note, owned_note_ids,
+    refresh_grantee,
+};
+use crate::leases::{assert_not_leased, foreign_lease};
+use crate::oplog::assert_not_op_log;
+use crate::storage::{NOTES, NFTS, NOTE_REVISIONS, REVISION_POLICIES, SHARE_EXPIRY_QUEUE};
+use crate::trash::move_to_trash;
+use crate::types::{
+    AuditAction, ChangeKind, Note, NoteId, NoteOrder, NoteRevision, NoteRevisionSummary, NoteUpdateError, Page,
+    PageRequest, PendingShare, RevisionPolicy, ShareError, ShareExpiration, ShareGrant,
+    SharePermission, UsernameShareResult, WorkspaceId,
+};
+use crate::user::find_user_by_username;
+
+/// Retention applied to notes without an explicit revision policy
+const DEFAULT_MAX_REVISIONS: u32 = 50;
+/// Maximum number of usernames accepted by `share_note_with_usernames`
+const MAX_BATCH_SHARE_USERNAMES: usize = 50;
+/// Most expired grants removed by a single run of the sweep job
+const MAX_EXPIRED_PER_RUN: usize = 500;
 
 /// Create a new encrypted note
 /// Returns the ID of the newly created note
@@ -28,15 +54,45 @@ pub fn create_note(encrypted: String) -> NoteId {
         max_size
     );
 
+    insert_note(caller, encrypted, 0, None)
+}
+
+/// Store a brand-new note owned by `owner` and index it
+/// `chunk_count` is non-zero for notes whose content lives in NOTE_CHUNKS
+pub fn insert_note(
+    owner: Principal,
+    encrypted: String,
+    chunk_count: u32,
+    workspace_id: Option<WorkspaceId>,
+) -> NoteId {
     let note_id = get_next_id();
+    let now = ic_cdk::api::time();
     let note = Note {
         id: note_id,
-        owner: caller,
+        owner,
         encrypted,
         shared_read: vec![],
         shared_edit: vec![],
+        version: 0,
+        created_at: now,
+        updated_at: now,
+        last_editor: owner,
+        trashed_at: None,
+        chunk_count,
+        share_expirations: vec![],
+        key_epoch: 0,
+        content_key_epoch: 0,
+        shared_read_groups: vec![],
+        shared_edit_groups: vec![],
+        workspace_id,
+        shared_comment: vec![],
+        shared_comment_groups: vec![],
+        folder_id: None,
+        op_log_clock: None,
     };
 
+    index_note(&note);
+    record_note_change(None, &note, ChangeKind::Created);
     NOTES.with_borrow_mut(|store| {
         store.insert(note_id, note);
     });
@@ -45,29 +101,31 @@ pub fn create_note(encrypted: String) -> NoteId {
 }
 
 /// Read all notes accessible to the caller
-/// Includes owned notes and notes shared with read permissions
+/// Includes owned notes and notes shared with read or edit permissions
+/// Unpaged wrapper around `read_notes_page`, kept until clients migrate
 #[update]
 pub fn read_notes() -> Vec<Note> {
+    collect_pages(read_notes_page)
+}
+
+/// Read one page of notes accessible to the caller, ordered by note ID
+#[query]
+pub fn read_notes_page(request: PageRequest) -> Page<Note> {
     let caller = msg_caller();
     let _ = assert_not_anonymous(&caller);
 
-    NOTES.with_borrow(|store| {
-        store
-            .iter()
-            .filter(|(_, note)| note.owner == caller || note.shared_read.contains(&caller))
-            .map(|(_, note)| note.clone())
-            .collect()
-    })
+    notes_page(accessible_note_ids(caller), &request, |note| note.can_read(&caller))
 }
 
 /// Get a specific note by ID
 /// Returns the note if caller has read permissions
-#[query]
+/// An update call so the read can be recorded in the note's audit log
+#[update]
 pub fn get_note(note_id: NoteId) -> Option<Note> {
     let caller = msg_caller();
     let _ = assert_not_anonymous(&caller);
 
-    NOTES.with_borrow(|store| {
+    let note = NOTES.with_borrow(|store| {
         if let Some(note) = store.get(&note_id) {
             if note.can_read(&caller) {
                 Some(note.clone())
@@ -77,13 +135,19 @@ pub fn get_note(note_id: NoteId) -> Option<Note> {
         } else {
             None
         }
-    })
+    });
+
+    if note.is_some() {
+        record_access(note_id, caller, AuditAction::Read);
+    }
+    note
 }
 
 /// Update an existing note's content
-/// Requires edit permissions on the note
+/// Requires edit permissions on the note, and content encrypted under the
+/// note's latest key epoch (`None` is accepted while the epoch is still 0)
 #[update]
-pub fn update_note(note_id: NoteId, new_encrypted: String) {
+pub fn update_note(note_id: NoteId, new_encrypted: String, key_epoch: Option<u64>) {
     let caller = msg_caller();
     let max_size = get_max_note_size();
     assert!(
@@ -98,8 +162,12 @@ pub fn update_note(note_id: NoteId, new_encrypted: String) {
             if !note.can_edit(&caller) {
                 ic_cdk::trap("Not authorized to update this note");
             }
+            assert_not_op_log(&note);
+            assert_not_leased(&note, &caller);
+            assert_latest_key_epoch(&note, key_epoch);
 
-            note.encrypted = new_encrypted;
+            replace_content(&mut note, new_encrypted, caller);
+            record_access(note_id, caller, AuditAction::Update { version: note.version });
             store.insert(note_id, note);
         } else {
             ic_cdk::trap("Note not found");
@@ -107,8 +175,63 @@ pub fn update_note(note_id: NoteId, new_encrypted: String) {
     });
 }
 
-/// Delete a note
-/// Only the owner can delete their notes
+/// Update a note's content only if it is still at `expected_version`
+/// Returns the new version, or a conflict carrying the current version so the
+/// client can merge instead of silently overwriting a concurrent edit
+#[update]
+pub fn update_note_versioned(
+    note_id: NoteId,
+    new_encrypted: String,
+    expected_version: u64,
+    key_epoch: Option<u64>,
+) -> Result<u64, NoteUpdateError> {
+    let caller = msg_caller();
+    let max_size = get_max_note_size();
+    if new_encrypted.len() > max_size {
+        return Err(NoteUpdateError::TooLarge {
+            size: new_encrypted.len() as u64,
+            max_size: max_size as u64,
+        });
+    }
+
+    NOTES.with_borrow_mut(|store| {
+        let mut note = store.get(&note_id).ok_or(NoteUpdateError::NotFound)?;
+        if !note.can_edit(&caller) {
+            return Err(NoteUpdateError::Unauthorized);
+        }
+        if note.op_log_clock.is_some() {
+            return Err(NoteUpdateError::OpLogMode);
+        }
+        if note.is_chunked() {
+            return Err(NoteUpdateError::Chunked);
+        }
+        if let Some(lease) = foreign_lease(&note, &caller) {
+            return Err(NoteUpdateError::Leased {
+                holder: lease.holder,
+                expires_at: lease.expires_at,
+            });
+        }
+        if note.version != expected_version {
+            return Err(NoteUpdateError::VersionConflict {
+                current_version: note.version,
+            });
+        }
+        if key_epoch.unwrap_or(0) != note.key_epoch {
+            return Err(NoteUpdateError::StaleKeyEpoch {
+                current_key_epoch: note.key_epoch,
+            });
+        }
+
+        replace_content(&mut note, new_encrypted, caller);
+        let version = note.version;
+        record_access(note_id, caller, AuditAction::Update { version });
+        store.insert(note_id, note);
+        Ok(version)
+    })
+}
+
+/// Delete a note by moving it to the owner's trash
+/// Only the owner can delete their notes; see trash.rs for restore and purge
 #[update]
 pub fn delete_note(note_id: NoteId) {
     let caller = msg_caller();
@@ -121,87 +244,359 @@ pub fn delete_note(note_id: NoteId) {
     }
 
     NOTES.with_borrow_mut(|store| {
-        if let Some(note) = store.get(&note_id) {
-            if note.owner != caller {
+        if let Some(mut note) = store.get(&note_id) {
+            if !note.is_managed_by(&caller) {
                 ic_cdk::trap("Only owner can delete");
             }
-            store.remove(&note_id);
+            if note.is_trashed() {
+                return;
+            }
+            move_to_trash(&mut note);
+            store.insert(note_id, note);
         }
     });
 }
 
 /// Share a note with read-only permissions
 /// Only the owner can share their notes
+/// The grant takes effect once the recipient accepts it from their inbox
 #[update]
 pub fn share_note_read(note_id: NoteId, user: Principal) {
-    let caller = msg_caller();
-
-    NOTES.with_borrow_mut(|store| {
-        if let Some(mut note) = store.get(&note_id) {
-            if note.owner != caller {
-                ic_cdk::trap("Only owner can share");
-            }
-
-            if !note.shared_read.contains(&user) {
-                note.shared_read.push(user);
-                store.insert(note_id, note);
-            }
-        }
-    });
+    grant_share(note_id, user, SharePermission::Read, None);
 }
 
 /// Share a note with edit permissions
 /// Only the owner can share their notes
+/// The grant takes effect once the recipient accepts it from their inbox
 #[update]
 pub fn share_note_edit(note_id: NoteId, user: Principal) {
+    grant_share(note_id, user, SharePermission::Edit, None);
+}
+
+/// Share a note with comment permissions: read and comment, but not edit
+/// Only the owner can share their notes
+/// The grant takes effect once the recipient accepts it from their inbox
+#[update]
+pub fn share_note_comment(note_id: NoteId, user: Principal) {
+    grant_share(note_id, user, SharePermission::Comment, None);
+}
+
+/// Share a note until `expires_at` (nanoseconds since epoch), or forever if `None`
+/// Calling it for an existing grant extends, shortens or clears its expiry
+/// Only the owner can share their notes
+#[update]
+pub fn share_note_with_expiry(
+    note_id: NoteId,
+    user: Principal,
+    permission: SharePermission,
+    expires_at: Option<u64>,
+) {
+    grant_share(note_id, user, permission, expires_at);
+}
+
+/// Share a note with the registered user holding `username`
+/// The username is resolved server-side, case-insensitively; returns the recipient's principal
+/// Only the owner can share their notes
+#[update]
+pub fn share_note_by_username(
+    note_id: NoteId,
+    username: String,
+    permission: SharePermission,
+    expires_at: Option<u64>,
+) -> Result<Principal, ShareError> {
     let caller = msg_caller();
 
-    NOTES.with_borrow_mut(|store| {
-        if let Some(mut note) = store.get(&note_id) {
-            if note.owner != caller {
-                ic_cdk::trap("Only owner can share");
-            }
+    let user = resolve_share_username(&username, &caller)?;
+    try_grant_share(note_id, caller, user, permission, expires_at)?;
+    Ok(user)
+}
 
-            if !note.shared_edit.contains(&user) {
-                note.shared_edit.push(user);
-                store.insert(note_id, note);
-            }
-        }
-    });
+/// Share a note with several usernames in one call
+/// Note-level failures (missing note, not the owner, trashed note) fail the
+/// whole call; otherwise each username gets its own result
+#[update]
+pub fn share_note_with_usernames(
+    note_id: NoteId,
+    usernames: Vec<String>,
+    permission: SharePermission,
+    expires_at: Option<u64>,
+) -> Result<Vec<UsernameShareResult>, ShareError> {
+    let caller = msg_caller();
+
+    if usernames.len() > MAX_BATCH_SHARE_USERNAMES {
+        return Err(ShareError::TooManyUsernames {
+            max: MAX_BATCH_SHARE_USERNAMES as u32,
+        });
+    }
+    let note = NOTES
+        .with_borrow(|store| store.get(&note_id))
+        .ok_or(ShareError::NoteNotFound)?;
+    check_can_share(&note, &caller)?;
+
+    Ok(usernames
+        .into_iter()
+        .map(|username| {
+            let result = resolve_share_username(&username, &caller).and_then(|user| {
+                try_grant_share(note_id, caller, user, permission, expires_at).map(|_| user)
+            });
+            UsernameShareResult { username, result }
+        })
+        .collect())
+}
+
+fn resolve_share_username(username: &str, caller: &Principal) -> Result<Principal, ShareError> {
+    if username.trim().is_empty() {
+        return Err(ShareError::InvalidUsername);
+    }
+
+    let user = find_user_by_username(username).ok_or_else(|| ShareError::UnknownUsername {
+        username: username.trim().to_string(),
+    })?;
+    if &user == caller {
+        return Err(ShareError::CannotShareWithSelf);
+    }
+    Ok(user)
 }
 
 /// Remove read permissions for a user
 /// Only the owner can manage sharing permissions
 #[update]
 pub fn unshare_note_read(note_id: NoteId, user: Principal) {
+    revoke_share(note_id, user, SharePermission::Read);
+}
+
+/// Remove edit permissions for a user
+/// Only the owner can manage sharing permissions
+#[update]
+pub fn unshare_note_edit(note_id: NoteId, user: Principal) {
+    revoke_share(note_id, user, SharePermission::Edit);
+}
+
+/// Remove comment permissions for a user
+/// Only the owner can manage sharing permissions
+#[update]
+pub fn unshare_note_comment(note_id: NoteId, user: Principal) {
+    revoke_share(note_id, user, SharePermission::Comment);
+}
+
+/// List every grant on a note together with its expiry
+/// Only the owner can see the full grant list
+#[query]
+pub fn list_note_shares(note_id: NoteId) -> Vec<ShareGrant> {
+    let caller = msg_caller();
+
+    NOTES.with_borrow(|store| match store.get(&note_id) {
+        Some(note) if note.is_managed_by(&caller) => note.grants(),
+        Some(_) => ic_cdk::trap("Only owner can list shares"),
+        None => ic_cdk::trap("Note not found"),
+    })
+}
+
+fn grant_share(
+    note_id: NoteId,
+    user: Principal,
+    permission: SharePermission,
+    expires_at: Option<u64>,
+) {
+    let caller = msg_caller();
+
+    match try_grant_share(note_id, caller, user, permission, expires_at) {
+        Ok(()) | Err(ShareError::NoteNotFound) => {}
+        Err(ShareError::ExpiryInPast) => ic_cdk::trap("Share expiry must be in the future"),
+        Err(ShareError::NotOwner) => ic_cdk::trap("Only owner can share"),
+        Err(ShareError::NoteTrashed) => ic_cdk::trap("Note is in the trash"),
+        Err(ShareError::Blocked) => ic_cdk::trap("Recipient does not accept shares from you"),
+        Err(error) => ic_cdk::trap(format!("Failed to share note: {error:?}")),
+    }
+}
+
+fn try_grant_share(
+    note_id: NoteId,
+    caller: Principal,
+    user: Principal,
+    permission: SharePermission,
+    expires_at: Option<u64>,
+) -> Result<(), ShareError> {
+    if let Some(expires_at) = expires_at {
+        if expires_at <= ic_cdk::api::time() {
+            return Err(ShareError::ExpiryInPast);
+        }
+    }
+
+    NOTES.with_borrow_mut(|store| {
+        let mut note = store.get(&note_id).ok_or(ShareError::NoteNotFound)?;
+        check_can_share(&note, &caller)?;
+
+        let offer = PendingShare {
+            note_id,
+            from: caller,
+            permission,
+            expires_at,
+            created_at: ic_cdk::api::time(),
+        };
+        let before = note.clone();
+        offer_share(&mut note, user, offer)?;
+        if note.grants() != before.grants() {
+            record_note_change(Some(&before), &note, ChangeKind::AccessChanged);
+        }
+        record_access(
+            note_id,
+            caller,
+            AuditAction::Share {
+                user,
+                permission,
+                expires_at,
+            },
+        );
+        store.insert(note_id, note);
+        Ok(())
+    })
+}
+
+/// Add or update a grant on `note`, replacing any previous expiry
+/// Callers are responsible for authorization and for storing the note
+pub fn apply_grant(
+    note: &mut Note,
+    user: Principal,
+    permission: SharePermission,
+    expires_at: Option<u64>,
+) {
+    let grantees = note.grantees_mut(permission);
+    if !grantees.contains(&user) {
+        grantees.push(user);
+    }
+    note.share_expirations
+        .retain(|e| !(e.user == user && e.permission == permission));
+    if let Some(expires_at) = expires_at {
+        note.share_expirations.push(ShareExpiration {
+            user,
+            permission,
+            expires_at,
+        });
+        SHARE_EXPIRY_QUEUE.with_borrow_mut(|queue| queue.insert((expires_at, note.id, user), ()));
+    }
+
+    refresh_grantee(note, user);
+}
+
+fn check_can_share(note: &Note, caller: &Principal) -> Result<(), ShareError> {
+    if !note.is_managed_by(caller) {
+        return Err(ShareError::NotOwner);
+    }
+    if note.is_trashed() {
+        return Err(ShareError::NoteTrashed);
+    }
+    Ok(())
+}
+
+fn revoke_share(note_id: NoteId, user: Principal, permission: SharePermission) {
     let caller = msg_caller();
 
     NOTES.with_borrow_mut(|store| {
         if let Some(mut note) = store.get(&note_id) {
-            if note.owner != caller {
+            if !note.is_managed_by(&caller) {
                 ic_cdk::trap("Only owner can unshare");
             }
-            note.shared_read.retain(|p| p != &user);
+            if note.is_trashed() {
+                ic_cdk::trap("Note is in the trash");
+            }
+
+            let before = note.clone();
+            remove_grant(&mut note, user, permission);
+            withdraw_offer(note_id, user, permission);
+            record_access(note_id, caller, AuditAction::Unshare { user, permission });
+            record_note_change(Some(&before), &note, ChangeKind::AccessChanged);
             store.insert(note_id, note);
         }
     });
 }
 
-/// Remove edit permissions for a user
-/// Only the owner can manage sharing permissions
-#[update]
-pub fn unshare_note_edit(note_id: NoteId, user: Principal) {
-    let caller = msg_caller();
+/// Remove every direct grant `user` holds on other principals' notes
+/// Returns the affected notes
+pub fn revoke_user_grants(user: Principal) -> Vec<NoteId> {
+    let note_ids = direct_grant_note_ids(user);
 
     NOTES.with_borrow_mut(|store| {
-        if let Some(mut note) = store.get(&note_id) {
-            if note.owner != caller {
-                ic_cdk::trap("Only owner can unshare");
+        for note_id in &note_ids {
+            if let Some(mut note) = store.get(note_id) {
+                let before = note.clone();
+                for permission in [
+                    SharePermission::Read,
+                    SharePermission::Edit,
+                    SharePermission::Comment,
+                ] {
+                    if note.grantees(permission).contains(&user) {
+                        remove_grant(&mut note, user, permission);
+                        record_access(*note_id, user, AuditAction::Unshare { user, permission });
+                    }
+                }
+                record_note_change(Some(&before), &note, ChangeKind::AccessChanged);
+                store.insert(*note_id, note);
             }
-            note.shared_edit.retain(|p| p != &user);
-            store.insert(note_id, note);
         }
     });
+    note_ids
+}
+
+/// Drop a grant and rotate the note's key epoch so the revoked user cannot
+/// derive the key for content written from now on
+fn remove_grant(note: &mut Note, user: Principal, permission: SharePermission) {
+    let had_grant = note.grantees(permission).contains(&user);
+
+    note.grantees_mut(permission).retain(|p| p != &user);
+    note.share_expirations
+        .retain(|e| !(e.user == user && e.permission == permission));
+    refresh_grantee(note, user);
+
+    if had_grant {
+        note.key_epoch += 1;
+    }
+}
+
+/// Remove the earliest grants whose expiry has passed, at most
+/// `MAX_EXPIRED_PER_RUN` per call
+/// Runs periodically from the timer registered in timers.rs; until it runs,
+/// expired grants are already rejected by `can_read`/`can_edit`
+pub fn sweep_expired_shares() {
+    let now = ic_cdk::api::time();
+
+    let due: Vec<(u64, NoteId, Principal)> = SHARE_EXPIRY_QUEUE.with_borrow(|queue| {
+        queue
+            .keys()
+            .take_while(|(expires_at, _, _)| *expires_at <= now)
+            .take(MAX_EXPIRED_PER_RUN)
+            .collect()
+    });
+
+    for (expires_at, note_id, user) in due {
+        SHARE_EXPIRY_QUEUE.with_borrow_mut(|queue| queue.remove(&(expires_at, note_id, user)));
+
+        NOTES.with_borrow_mut(|store| {
+            if let Some(mut note) = store.get(&note_id) {
+                let expired: Vec<SharePermission> = note
+                    .share_expirations
+                    .iter()
+                    .filter(|e| e.user == user && e.expires_at <= now)
+                    .map(|e| e.permission)
+                    .collect();
+                if expired.is_empty() {
+                    return;
+                }
+
+                let before = note.clone();
+                for permission in expired {
+                    remove_grant(&mut note, user, permission);
+                    record_access(
+                        note_id,
+                        ic_cdk::api::canister_self(),
+                        AuditAction::ShareExpired { user, permission },
+                    );
+                }
+                record_note_change(Some(&before), &note, ChangeKind::AccessChanged);
+                store.insert(note_id, note);
+            }
+        });
+    }
 }
 
 /// Get public key for symmetric key verification for notes
@@ -223,14 +618,48 @@ pub async fn symmetric_key_verification_key_for_note() -> String {
 
 /// Get encrypted symmetric key for a specific note
 /// Used in the vetKD protocol for note decryption
+/// Derives the key for the note's latest key epoch
 #[update]
 pub async fn encrypted_symmetric_key_for_note(
     note_id: NoteId,
     transport_public_key: Vec<u8>,
+) -> String {
+    derive_note_key(note_id, None, transport_public_key).await
+}
+
+/// Get encrypted symmetric key for a specific key epoch of a note
+/// Needed to decrypt content, revisions and attachments written before the
+/// latest rotation; `key_epoch` must not exceed the note's current epoch
+#[update]
+pub async fn encrypted_symmetric_key_for_note_epoch(
+    note_id: NoteId,
+    key_epoch: u64,
+    transport_public_key: Vec<u8>,
+) -> String {
+    derive_note_key(note_id, Some(key_epoch), transport_public_key).await
+}
+
+/// Get the latest key epoch of a note
+/// New content must be encrypted under this epoch; requires read permissions
+#[query]
+pub fn get_note_key_epoch(note_id: NoteId) -> u64 {
+    let caller = msg_caller();
+
+    NOTES.with_borrow(|store| match store.get(&note_id) {
+        Some(note) if note.can_read(&caller) => note.key_epoch,
+        Some(_) => ic_cdk::trap("Not authorized to read this note"),
+        None => ic_cdk::trap("Note not found"),
+    })
+}
+
+async fn derive_note_key(
+    note_id: NoteId,
+    key_epoch: Option<u64>,
+    transport_public_key: Vec<u8>,
 ) -> String {
     let caller = msg_caller();
 
-    let request = NOTES.with_borrow(|notes| {
+    let (input, key_epoch) = NOTES.with_borrow(|notes| {
         if let Some(note) = notes.get(&note_id) {
             if !note.can_read(&caller) {
                 ic_cdk::trap(format!(
@@ -239,22 +668,34 @@ pub async fn encrypted_symmetric_key_for_note(
                 ));
             }
 
-            VetKDDeriveKeyArgs {
-                input: {
-                    let mut buf = vec![];
-                    buf.extend_from_slice(&note_id.to_be_bytes());
-                    buf.extend_from_slice(&note.owner.to_bytes());
-                    buf
-                },
-                context: b"note_symmetric_key".to_vec(),
-                key_id: crate::helpers::bls12_381_g2_test_key_1(),
-                transport_public_key,
+            let key_epoch = key_epoch.unwrap_or(note.key_epoch);
+            if key_epoch > note.key_epoch {
+                ic_cdk::trap(format!(
+                    "key epoch {key_epoch} does not exist for note {note_id}"
+                ));
             }
+
+            (note_key_derivation_input(&note, key_epoch), key_epoch)
         } else {
             ic_cdk::trap(format!("note with ID {note_id} does not exist"));
         }
     });
 
+    let encrypted_key = derive_encrypted_key(input, transport_public_key).await;
+    record_access(note_id, caller, AuditAction::KeyRequest { key_epoch });
+    encrypted_key
+}
+
+/// Run vetKD for a note key derivation input, encrypted to `transport_public_key`
+/// Callers are responsible for checking that the caller may obtain this key
+pub async fn derive_encrypted_key(input: Vec<u8>, transport_public_key: Vec<u8>) -> String {
+    let request = VetKDDeriveKeyArgs {
+        input,
+        context: b"note_symmetric_key".to_vec(),
+        key_id: crate::helpers::bls12_381_g2_test_key_1(),
+        transport_public_key,
+    };
+
     let response: VetKDDeriveKeyResult = ic_cdk::management_canister::vetkd_derive_key(&request)
         .await
         .expect("call to vetkd_derive_key failed");
@@ -262,38 +703,81 @@ pub async fn encrypted_symmetric_key_for_note(
     hex::encode(response.encrypted_key)
 }
 
+/// vetKD derivation input: `note_id || owner`, followed by the epoch once it
+/// has been rotated, so epoch 0 keeps the key notes had before rotation existed
+pub fn key_derivation_input(note_id: NoteId, owner: &Principal, key_epoch: u64) -> Vec<u8> {
+    let mut buf = vec![];
+    buf.extend_from_slice(&note_id.to_be_bytes());
+    buf.extend_from_slice(&owner.to_bytes());
+    if key_epoch > 0 {
+        buf.extend_from_slice(&key_epoch.to_be_bytes());
+    }
+    buf
+}
+
+/// Derivation input for a stored note
+/// Workspace notes derive from the workspace instead of `owner`, so their key
+/// survives the note being reassigned to another member
+pub fn note_key_derivation_input(note: &Note, key_epoch: u64) -> Vec<u8> {
+    match note.workspace_id {
+        Some(workspace_id) => {
+            let mut buf = vec![];
+            buf.extend_from_slice(&note.id.to_be_bytes());
+            buf.extend_from_slice(b"workspace");
+            buf.extend_from_slice(&workspace_id.to_be_bytes());
+            if key_epoch > 0 {
+                buf.extend_from_slice(&key_epoch.to_be_bytes());
+            }
+            buf
+        }
+        None => key_derivation_input(note.id, &note.owner, key_epoch),
+    }
+}
+
+/// Trap unless content declared as encrypted under `key_epoch` may be written to `note`
+/// Legacy callers that pass no epoch are accepted until the note is first rotated
+pub fn assert_latest_key_epoch(note: &Note, key_epoch: Option<u64>) {
+    if key_epoch.unwrap_or(0) != note.key_epoch {
+        ic_cdk::trap(format!(
+            "Content must be re-encrypted under key epoch {}",
+            note.key_epoch
+        ));
+    }
+}
+
 /// Get notes owned by the caller
 /// Returns only notes where the caller is the owner
+/// Unpaged wrapper around `get_my_notes_page`, kept until clients migrate
 #[query]
 pub fn get_my_notes() -> Vec<Note> {
+    collect_pages(get_my_notes_page)
+}
+
+/// Get one page of notes owned by the caller, ordered by note ID
+#[query]
+pub fn get_my_notes_page(request: PageRequest) -> Page<Note> {
     let caller = msg_caller();
     let _ = assert_not_anonymous(&caller);
 
-    NOTES.with_borrow(|store| {
-        store
-            .iter()
-            .filter(|(_, note)| note.owner == caller)
-            .map(|(_, note)| note.clone())
-            .collect()
-    })
+    notes_page(owned_note_ids(caller), &request, |_| true)
 }
 
 /// Get notes shared with the caller
 /// Returns notes where the caller has been granted read or edit permissions
+/// Unpaged wrapper around `get_shared_notes_page`, kept until clients migrate
 #[query]
 pub fn get_shared_notes() -> Vec<Note> {
+    collect_pages(get_shared_notes_page)
+}
+
+/// Get one page of notes shared with the caller, ordered by note ID
+#[query]
+pub fn get_shared_notes_page(request: PageRequest) -> Page<Note> {
     let caller = msg_caller();
     let _ = assert_not_anonymous(&caller);
 
-    NOTES.with_borrow(|store| {
-        store
-            .iter()
-            .filter(|(_, note)| {
-                note.owner != caller && 
-                (note.shared_read.contains(&caller) || note.shared_edit.contains(&caller))
-            })
-            .map(|(_, note)| note.clone())
-            .collect()
+    notes_page(granted_note_ids(caller), &request, |note| {
+        note.owner != caller && note.can_read(&caller)
     })
 }
 
@@ -304,10 +788,348 @@ pub fn get_note_count() -> u64 {
     let caller = msg_caller();
     let _ = assert_not_anonymous(&caller);
 
+    let note_ids = accessible_note_ids(caller);
     NOTES.with_borrow(|store| {
-        store
-            .iter()
-            .filter(|(_, note)| note.can_read(&caller))
+        note_ids
+            .into_iter()
+            .filter_map(|id| store.get(&id))
+            .filter(|note| !note.is_trashed())
             .count() as u64
     })
 }
+
+/// Read all notes accessible to the caller, sorted by creation or update time
+#[query]
+pub fn read_notes_sorted(order: NoteOrder) -> Vec<Note> {
+    let mut notes = read_notes();
+    sort_notes(&mut notes, order);
+    notes
+}
+
+/// Get notes owned by the caller, sorted by creation or update time
+#[query]
+pub fn get_my_notes_sorted(order: NoteOrder) -> Vec<Note> {
+    let mut notes = get_my_notes();
+    sort_notes(&mut notes, order);
+    notes
+}
+
+/// Get notes shared with the caller, sorted by creation or update time
+#[query]
+pub fn get_shared_notes_sorted(order: NoteOrder) -> Vec<Note> {
+    let mut notes = get_shared_notes();
+    sort_notes(&mut notes, order);
+    notes
+}
+
+/// List archived revisions of a note, oldest first
+/// Requires read permissions on the note; ciphertexts are omitted
+#[query]
+pub fn list_note_revisions(note_id: NoteId) -> Vec<NoteRevisionSummary> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    assert_can_read(note_id, &caller);
+
+    NOTE_REVISIONS.with_borrow(|store| {
+        store
+            .range(revision_range(note_id))
+            .map(|(_, revision)| NoteRevisionSummary {
+                revision_id: revision.revision_id,
+                author: revision.author,
+                created_at: revision.created_at,
+                size: revision.encrypted.len() as u64,
+            })
+            .collect()
+    })
+}
+
+/// Get a specific archived revision of a note
+/// Returns the revision if caller has read permissions
+#[query]
+pub fn get_note_revision(note_id: NoteId, revision_id: u64) -> Option<NoteRevision> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let readable = NOTES.with_borrow(|store| {
+        store
+            .get(&note_id)
+            .map(|note| note.can_read(&caller))
+            .unwrap_or(false)
+    });
+    if !readable {
+        return None;
+    }
+
+    NOTE_REVISIONS.with_borrow(|store| store.get(&(note_id, revision_id)))
+}
+
+/// Restore an archived revision as the current note content
+/// Requires edit permissions; the replaced content is archived as a new revision
+/// Revisions from an older key epoch must be re-encrypted and saved with `update_note` instead
+#[update]
+pub fn restore_note_revision(note_id: NoteId, revision_id: u64) {
+    let caller = msg_caller();
+
+    let revision = NOTE_REVISIONS
+        .with_borrow(|store| store.get(&(note_id, revision_id)))
+        .unwrap_or_else(|| ic_cdk::trap("Revision not found"));
+
+    NOTES.with_borrow_mut(|store| {
+        if let Some(mut note) = store.get(&note_id) {
+            if !note.can_edit(&caller) {
+                ic_cdk::trap("Not authorized to update this note");
+            }
+            assert_not_op_log(&note);
+            assert_not_leased(&note, &caller);
+            assert_latest_key_epoch(&note, revision.key_epoch);
+
+            replace_content(&mut note, revision.encrypted, caller);
+            record_access(
+                note_id,
+                caller,
+                AuditAction::RevisionRestored {
+                    revision_id,
+                    version: note.version,
+                },
+            );
+            store.insert(note_id, note);
+        } else {
+            ic_cdk::trap("Note not found");
+        }
+    });
+}
+
+/// Set the revision retention policy for a note
+/// Only controllers can configure retention; `None` restores the default policy
+#[update]
+pub fn set_revision_policy(note_id: NoteId, policy: Option<RevisionPolicy>) -> Result<(), String> {
+    let caller = msg_caller();
+
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can set revision policies".to_string());
+    }
+
+    if !NOTES.with_borrow(|store| store.contains_key(&note_id)) {
+        return Err("Note not found".to_string());
+    }
+
+    REVISION_POLICIES.with_borrow_mut(|policies| match policy {
+        Some(policy) => policies.insert(note_id, policy),
+        None => policies.remove(&note_id),
+    });
+    prune_revisions(note_id, ic_cdk::api::time());
+
+    Ok(())
+}
+
+/// Get the revision retention policy in effect for a note
+/// Requires read permissions on the note
+#[query]
+pub fn get_revision_policy(note_id: NoteId) -> RevisionPolicy {
+    let caller = msg_caller();
+    assert_can_read(note_id, &caller);
+
+    effective_revision_policy(note_id)
+}
+
+/// Page through `note_ids` (ascending) after the request cursor, loading only
+/// the notes that make it into the page; trashed notes are skipped
+pub fn notes_page(
+    note_ids: Vec<NoteId>,
+    request: &PageRequest,
+    keep: impl Fn(&Note) -> bool,
+) -> Page<Note> {
+    let after: Option<NoteId> = parse_cursor(request);
+
+    NOTES.with_borrow(|store| {
+        let entries = note_ids
+            .into_iter()
+            .filter(|id| after.is_none_or(|cursor| *id > cursor))
+            .filter_map(|id| store.get(&id))
+            .filter(|note| !note.is_trashed() && keep(note))
+            .map(|note| (note.id, note));
+        paginate(entries, request)
+    })
+}
+
+/// Trap unless `caller` can read the note
+pub fn assert_can_read(note_id: NoteId, caller: &Principal) {
+    NOTES.with_borrow(|store| match store.get(&note_id) {
+        Some(note) if note.can_read(caller) => {}
+        Some(_) => ic_cdk::trap("Not authorized to read this note"),
+        None => ic_cdk::trap("Note not found"),
+    });
+}
+
+/// Trap unless `caller` can edit the note
+pub fn assert_can_edit(note_id: NoteId, caller: &Principal) {
+    NOTES.with_borrow(|store| match store.get(&note_id) {
+        Some(note) if note.can_edit(caller) => {}
+        Some(_) => ic_cdk::trap("Not authorized to update this note"),
+        None => ic_cdk::trap("Note not found"),
+    });
+}
+
+fn revision_range(note_id: NoteId) -> std::ops::RangeInclusive<(NoteId, u64)> {
+    (note_id, 0)..=(note_id, u64::MAX)
+}
+
+fn effective_revision_policy(note_id: NoteId) -> RevisionPolicy {
+    REVISION_POLICIES
+        .with_borrow(|policies| policies.get(&note_id))
+        .unwrap_or(RevisionPolicy {
+            max_revisions: Some(DEFAULT_MAX_REVISIONS),
+            max_age_nanos: None,
+        })
+}
+
+/// Swap in new ciphertext, archiving the old one and bumping the version
+/// Callers must have checked that the new content uses the latest key epoch
+/// Chunked notes are rejected; their content only changes by a new chunked upload
+pub fn replace_content(note: &mut Note, new_encrypted: String, editor: Principal) {
+    assert_not_chunked(note);
+    archive_revision(note);
+    set_content(note, new_encrypted, editor);
+}
+
+/// Swap in the chunks of a committed upload, bumping the version
+/// Inline content is archived; chunked content is not and its chunks are dropped
+pub fn replace_with_chunks(note: &mut Note, chunk_count: u32, editor: Principal) {
+    if note.is_chunked() {
+        clear_note_chunks(note.id);
+    } else {
+        archive_revision(note);
+    }
+    set_content(note, String::new(), editor);
+    note.chunk_count = chunk_count;
+}
+
+fn set_content(note: &mut Note, new_encrypted: String, editor: Principal) {
+    note.encrypted = new_encrypted;
+    note.content_key_epoch = note.key_epoch;
+    note.version += 1;
+    note.updated_at = ic_cdk::api::time();
+    note.last_editor = editor;
+    record_note_change(None, note, ChangeKind::Updated);
+}
+
+fn sort_notes(notes: &mut [Note], order: NoteOrder) {
+    match order {
+        NoteOrder::CreatedAsc => notes.sort_by_key(|note| (note.created_at, note.id)),
+        NoteOrder::CreatedDesc => notes.sort_by_key(|note| Reverse((note.created_at, note.id))),
+        NoteOrder::UpdatedAsc => notes.sort_by_key(|note| (note.updated_at, note.id)),
+        NoteOrder::UpdatedDesc => notes.sort_by_key(|note| Reverse((note.updated_at, note.id))),
+    }
+}
+
+/// Archive the current ciphertext of `note` before it gets replaced
+/// The revision keeps the author and time of that ciphertext, not of its replacement
+fn archive_revision(note: &Note) {
+    let now = ic_cdk::api::time();
+
+    NOTE_REVISIONS.with_borrow_mut(|store| {
+        let revision_id = store
+            .keys_range(revision_range(note.id))
+            .next_back()
+            .map(|(_, last)| last + 1)
+            .unwrap_or(1);
+
+        store.insert(
+            (note.id, revision_id),
+            NoteRevision {
+                note_id: note.id,
+                revision_id,
+                encrypted: note.encrypted.clone(),
+                author: note.last_editor,
+                created_at: note.updated_at,
+                key_epoch: Some(note.content_key_epoch),
+            },
+        );
+    });
+
+    prune_revisions(note.id, now);
+}
+
+/// Drop revisions that fall outside the note's retention policy
+fn prune_revisions(note_id: NoteId, now: u64) {
+    let policy = effective_revision_policy(note_id);
+
+    NOTE_REVISIONS.with_borrow_mut(|store| {
+        let mut expired: Vec<(NoteId, u64)> = Vec::new();
+
+        if let Some(max_revisions) = policy.max_revisions {
+            let count = store.keys_range(revision_range(note_id)).count();
+            let excess = count.saturating_sub(max_revisions as usize);
+            expired.extend(store.keys_range(revision_range(note_id)).take(excess));
+        }
+
+        if let Some(max_age) = policy.max_age_nanos {
+            let cutoff = now.saturating_sub(max_age);
+            expired.extend(
+                store
+                    .range(revision_range(note_id))
+                    .take_while(|(_, revision)| revision.created_at < cutoff)
+                    .map(|(key, _)| key),
+            );
+        }
+
+        for key in expired {
+            store.remove(&key);
+        }
+    });
+}
+
+/// Remove every archived revision of a note
+pub fn clear_revisions(note_id: NoteId) {
+    NOTE_REVISIONS.with_borrow_mut(|store| {
+        let keys: Vec<(NoteId, u64)> = store.keys_range(revision_range(note_id)).collect();
+        for key in keys {
+            store.remove(&key);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note, principal};
+
+    #[test]
+    fn test_epoch_zero_keeps_legacy_derivation_input() {
+        let owner = principal(1);
+        let mut legacy = 7u128.to_be_bytes().to_vec();
+        legacy.extend_from_slice(owner.as_slice());
+
+        assert_eq!(key_derivation_input(7, &owner, 0), legacy);
+        assert_ne!(
+            key_derivation_input(7, &owner, 1),
+            key_derivation_input(7, &owner, 2)
+        );
+    }
+
+    #[test]
+    fn test_revoking_a_grant_rotates_key_epoch() {
+        let (alice, bob) = (principal(2), principal(3));
+        let mut shared = note(1, alice);
+        shared.shared_read.push(bob);
+
+        remove_grant(&mut shared, bob, SharePermission::Edit);
+        assert_eq!(shared.key_epoch, 0);
+
+        remove_grant(&mut shared, bob, SharePermission::Read);
+        assert_eq!(shared.key_epoch, 1);
+        assert!(shared.shared_read.is_empty());
+    }
+
+    #[test]
+    fn test_workspace_key_survives_reassignment() {
+        let (alice, bob) = (principal(4), principal(5));
+        let mut shared = note(2, alice);
+        shared.workspace_id = Some(9);
+        let before = note_key_derivation_input(&shared, 0);
+
+        shared.owner = bob;
+        assert_eq!(note_key_derivation_input(&shared, 0), before);
+        assert_ne!(note_key_derivation_input(&note(2, alice), 0), before);
+    }
+}
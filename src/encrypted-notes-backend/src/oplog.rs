@@ -0,0 +1,416 @@
+// Operation Log Module
+// src/encrypted-notes-backend/src/oplog.rs
+//
+// Notes in op-log mode are edited through an append-only log of
+// client-encrypted CRDT operations instead of whole-ciphertext replacement.
+// Each operation carries its author, a Lamport clock and the operations it
+// depends on; clients decrypt the log and merge concurrent edits themselves.
+// The note's `encrypted` content is a snapshot covering every operation up
+// to `op_log_clock`. Any editor can compact the log by submitting a newer
+// snapshot, after which the operations it covers are dropped.
+
+use std::collections::BTreeSet;
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::audit::record_access;
+use crate::changes::record_note_change;
+use crate::helpers::{
+    assert_not_anonymous, get_max_note_size, get_next_id, page_size, parse_cursor,
+};
+use crate::leases::assert_not_leased;
+use crate::note::{assert_latest_key_epoch, replace_content};
+use crate::storage::{NOTES, NOTE_OPS};
+use crate::types::{
+    AuditAction, ChangeKind, NewNoteOp, Note, NoteId, NoteOp, NoteOpBatch, OpId, PageRequest,
+};
+
+/// Maximum size of a single encrypted operation (64 KiB)
+const MAX_OP_SIZE: usize = 64 * 1024;
+/// Maximum number of operations appended in one call
+const MAX_OPS_PER_CALL: usize = 100;
+/// Maximum number of dependencies of a single operation
+const MAX_OP_DEPS: usize = 64;
+/// Operations a note may accumulate before it must be compacted
+const MAX_PENDING_OPS: usize = 10_000;
+
+/// Switch a note to op-log mode, with its current content as the initial snapshot
+/// Only the owner can change the mode
+#[update]
+pub fn enable_note_op_log(note_id: NoteId) {
+    let caller = msg_caller();
+
+    NOTES.with_borrow_mut(|store| {
+        let mut note = managed_note(store.get(&note_id), &caller);
+        if note.op_log_clock.is_some() {
+            return;
+        }
+        note.op_log_clock = Some(0);
+        record_note_change(None, &note, ChangeKind::Updated);
+        store.insert(note_id, note);
+    });
+}
+
+/// Switch a note back to whole-ciphertext updates
+/// Only the owner can change the mode; the log must be fully compacted first
+#[update]
+pub fn disable_note_op_log(note_id: NoteId) {
+    let caller = msg_caller();
+
+    NOTES.with_borrow_mut(|store| {
+        let mut note = managed_note(store.get(&note_id), &caller);
+        if note.op_log_clock.is_none() {
+            return;
+        }
+        if has_pending_ops(note_id) {
+            ic_cdk::trap("Compact the operation log before leaving op-log mode");
+        }
+        note.op_log_clock = None;
+        record_note_change(None, &note, ChangeKind::Updated);
+        store.insert(note_id, note);
+    });
+}
+
+/// Append operations to a note in op-log mode, returning the log position of the last one
+/// Requires edit permissions; operations must be encrypted under the note's latest key epoch
+/// Every dependency must be in the log, covered by the snapshot, or earlier in the same call
+#[update]
+pub fn append_note_ops(note_id: NoteId, ops: Vec<NewNoteOp>, key_epoch: u64) -> u128 {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    if ops.is_empty() {
+        ic_cdk::trap("No operations to append");
+    }
+    if ops.len() > MAX_OPS_PER_CALL {
+        ic_cdk::trap(format!(
+            "Too many operations: at most {} per call",
+            MAX_OPS_PER_CALL
+        ));
+    }
+
+    let mut note = editable_note(note_id, &caller);
+    let snapshot_clock = op_log_clock(&note);
+    assert_not_leased(&note, &caller);
+    assert_latest_key_epoch(&note, Some(key_epoch));
+
+    let known: BTreeSet<OpId> = NOTE_OPS.with_borrow(|log| {
+        log.range(note_range(note_id))
+            .map(|(_, op)| op.id)
+            .collect()
+    });
+    if known.len() + ops.len() > MAX_PENDING_OPS {
+        ic_cdk::trap("Too many pending operations; compact the note first");
+    }
+    if let Err(e) = validate_ops(snapshot_clock, &known, caller, &ops) {
+        ic_cdk::trap(e);
+    }
+
+    let now = ic_cdk::api::time();
+    let mut seq = 0;
+    NOTE_OPS.with_borrow_mut(|log| {
+        for op in ops {
+            seq = get_next_id();
+            log.insert(
+                (note_id, seq),
+                NoteOp {
+                    note_id,
+                    seq,
+                    id: OpId {
+                        clock: op.clock,
+                        author: caller,
+                    },
+                    deps: op.deps,
+                    encrypted: op.encrypted,
+                    key_epoch,
+                    created_at: now,
+                },
+            );
+        }
+    });
+
+    note.updated_at = now;
+    note.last_editor = caller;
+    record_note_change(None, &note, ChangeKind::Updated);
+    NOTES.with_borrow_mut(|store| store.insert(note_id, note));
+    seq
+}
+
+/// Get a note's operations appended after log position `request.cursor`, in arrival order
+/// Requires read permissions; operations arriving late with an old clock are still
+/// delivered, and clients order them by their clocks and dependencies
+#[query]
+pub fn get_note_ops(note_id: NoteId, request: PageRequest) -> NoteOpBatch {
+    let caller = msg_caller();
+
+    let note = match NOTES.with_borrow(|store| store.get(&note_id)) {
+        Some(note) if note.can_read(&caller) => note,
+        Some(_) => ic_cdk::trap("Not authorized to read this note"),
+        None => ic_cdk::trap("Note not found"),
+    };
+    let snapshot_clock = op_log_clock(&note);
+    let since = parse_cursor::<u128>(&request).unwrap_or(0);
+    let (ops, has_more) = ops_after(note_id, since, page_size(&request));
+
+    NoteOpBatch {
+        cursor: ops.last().map_or(since, |op| op.seq),
+        ops,
+        snapshot_clock,
+        has_more,
+    }
+}
+
+/// Replace the note's snapshot with one covering every operation up to `up_to_clock`
+/// and drop those operations from the log
+/// `seen_seq` is the highest log position the client had fetched; the call is
+/// rejected if operations it did not see would be folded into the snapshot
+#[update]
+pub fn compact_note_ops(
+    note_id: NoteId,
+    snapshot: String,
+    up_to_clock: u64,
+    seen_seq: u128,
+    key_epoch: u64,
+) {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let max_size = get_max_note_size();
+    if snapshot.len() > max_size {
+        ic_cdk::trap(format!(
+            "Note too large: {} bytes exceeds limit of {} bytes",
+            snapshot.len(),
+            max_size
+        ));
+    }
+
+    let mut note = editable_note(note_id, &caller);
+    if up_to_clock < op_log_clock(&note) {
+        ic_cdk::trap("The note was already compacted past this clock");
+    }
+    assert_not_leased(&note, &caller);
+    assert_latest_key_epoch(&note, Some(key_epoch));
+
+    let covered: Vec<NoteOp> = NOTE_OPS.with_borrow(|log| {
+        log.range(note_range(note_id))
+            .map(|(_, op)| op)
+            .filter(|op| op.id.clock <= up_to_clock)
+            .collect()
+    });
+    if covered.iter().any(|op| op.seq > seen_seq) {
+        ic_cdk::trap("Operations were appended since the snapshot was built; fetch them and retry");
+    }
+
+    replace_content(&mut note, snapshot, caller);
+    note.op_log_clock = Some(up_to_clock);
+    record_access(
+        note_id,
+        caller,
+        AuditAction::Update {
+            version: note.version,
+        },
+    );
+    NOTES.with_borrow_mut(|store| store.insert(note_id, note));
+
+    NOTE_OPS.with_borrow_mut(|log| {
+        for op in covered {
+            log.remove(&(note_id, op.seq));
+        }
+    });
+}
+
+/// Trap if `note` is in op-log mode, where content only changes through operations
+pub fn assert_not_op_log(note: &Note) {
+    if note.op_log_clock.is_some() {
+        ic_cdk::trap("Note is in op-log mode; append operations instead");
+    }
+}
+
+/// Whether a note has operations not yet folded into its snapshot
+pub fn has_pending_ops(note_id: NoteId) -> bool {
+    NOTE_OPS.with_borrow(|log| log.keys_range(note_range(note_id)).next().is_some())
+}
+
+/// Remove every operation of a note
+pub fn clear_note_ops(note_id: NoteId) {
+    let keys: Vec<(NoteId, u128)> =
+        NOTE_OPS.with_borrow(|log| log.keys_range(note_range(note_id)).collect());
+
+    NOTE_OPS.with_borrow_mut(|log| {
+        for key in keys {
+            log.remove(&key);
+        }
+    });
+}
+
+fn note_range(note_id: NoteId) -> std::ops::RangeInclusive<(NoteId, u128)> {
+    (note_id, 0)..=(note_id, u128::MAX)
+}
+
+fn managed_note(note: Option<Note>, caller: &Principal) -> Note {
+    match note {
+        Some(note) if note.is_managed_by(caller) => note,
+        Some(_) => ic_cdk::trap("Only the owner can change the note mode"),
+        None => ic_cdk::trap("Note not found"),
+    }
+}
+
+fn editable_note(note_id: NoteId, caller: &Principal) -> Note {
+    match NOTES.with_borrow(|store| store.get(&note_id)) {
+        Some(note) if note.can_edit(caller) => note,
+        Some(_) => ic_cdk::trap("Not authorized to update this note"),
+        None => ic_cdk::trap("Note not found"),
+    }
+}
+
+fn op_log_clock(note: &Note) -> u64 {
+    note.op_log_clock
+        .unwrap_or_else(|| ic_cdk::trap("Note is not in op-log mode"))
+}
+
+/// Check a batch of operations by `author` against the operations already known
+fn validate_ops(
+    snapshot_clock: u64,
+    known: &BTreeSet<OpId>,
+    author: Principal,
+    ops: &[NewNoteOp],
+) -> Result<(), String> {
+    let mut batch: BTreeSet<OpId> = BTreeSet::new();
+
+    for op in ops {
+        if op.encrypted.len() > MAX_OP_SIZE {
+            return Err(format!(
+                "Operation too large: {} bytes exceeds limit of {} bytes",
+                op.encrypted.len(),
+                MAX_OP_SIZE
+            ));
+        }
+        if op.deps.len() > MAX_OP_DEPS {
+            return Err(format!(
+                "Too many dependencies: at most {} per operation",
+                MAX_OP_DEPS
+            ));
+        }
+        if op.clock <= snapshot_clock {
+            return Err(format!(
+                "Operation clock {} is already covered by the snapshot at {}",
+                op.clock, snapshot_clock
+            ));
+        }
+
+        let id = OpId {
+            clock: op.clock,
+            author,
+        };
+        if known.contains(&id) || batch.contains(&id) {
+            return Err(format!("Duplicate operation at clock {}", op.clock));
+        }
+        for dep in &op.deps {
+            if dep.clock >= op.clock {
+                return Err(format!(
+                    "Dependency at clock {} is not before operation clock {}",
+                    dep.clock, op.clock
+                ));
+            }
+            if dep.clock > snapshot_clock && !known.contains(dep) && !batch.contains(dep) {
+                return Err(format!("Unknown dependency at clock {}", dep.clock));
+            }
+        }
+        batch.insert(id);
+    }
+
+    Ok(())
+}
+
+/// Up to `limit` operations of a note after log position `since`, and whether more follow
+fn ops_after(note_id: NoteId, since: u128, limit: usize) -> (Vec<NoteOp>, bool) {
+    NOTE_OPS.with_borrow(|log| {
+        let mut ops: Vec<NoteOp> = log
+            .range((note_id, since.saturating_add(1))..=(note_id, u128::MAX))
+            .map(|(_, op)| op)
+            .take(limit + 1)
+            .collect();
+        let has_more = ops.len() > limit;
+        ops.truncate(limit);
+        (ops, has_more)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::principal;
+
+    fn new_op(clock: u64, deps: Vec<OpId>) -> NewNoteOp {
+        NewNoteOp {
+            clock,
+            deps,
+            encrypted: String::new(),
+        }
+    }
+
+    fn stored_op(seq: u128, clock: u64, author: Principal) -> NoteOp {
+        NoteOp {
+            note_id: 1,
+            seq,
+            id: OpId { clock, author },
+            deps: vec![],
+            encrypted: String::new(),
+            key_epoch: 0,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_validate_ops_checks_clocks_and_dependencies() {
+        let (alice, bob) = (principal(1), principal(2));
+        let known: BTreeSet<OpId> = [OpId {
+            clock: 6,
+            author: bob,
+        }]
+        .into();
+        let op_id = |clock, author| OpId { clock, author };
+
+        // Dependencies may be in the log, in the snapshot or earlier in the batch
+        let ops = [
+            new_op(7, vec![op_id(6, bob), op_id(3, bob)]),
+            new_op(8, vec![op_id(7, alice)]),
+        ];
+        assert!(validate_ops(5, &known, alice, &ops).is_ok());
+
+        assert!(validate_ops(5, &known, alice, &[new_op(5, vec![])]).is_err());
+        assert!(validate_ops(5, &known, bob, &[new_op(6, vec![])]).is_err());
+        assert!(validate_ops(5, &known, alice, &[new_op(6, vec![op_id(6, bob)])]).is_err());
+        assert!(validate_ops(5, &known, alice, &[new_op(9, vec![op_id(8, bob)])]).is_err());
+    }
+
+    #[test]
+    fn test_late_op_with_old_clock_is_delivered_after_cursor() {
+        let (alice, bob) = (principal(1), principal(2));
+        NOTE_OPS.with_borrow_mut(|log| {
+            for op in [
+                stored_op(1, 1, alice),
+                stored_op(2, 5, alice),
+                stored_op(3, 2, bob),
+            ] {
+                log.insert((1, op.seq), op);
+            }
+        });
+
+        let (first, has_more) = ops_after(1, 0, 2);
+        assert_eq!(
+            first.iter().map(|op| op.seq).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(has_more);
+
+        // Bob's offline edit arrived last with a clock below the cursor's
+        let (rest, has_more) = ops_after(1, 2, 2);
+        let ids: Vec<(u64, Principal)> =
+            rest.iter().map(|op| (op.id.clock, op.id.author)).collect();
+        assert_eq!(ids, vec![(2, bob)]);
+        assert!(!has_more);
+    }
+}
@@ -1,6 +1,7 @@
 // Search Index Management Module
 // src/encrypted-notes-backend/src/search.rs
 
+use candid::Principal;
 use ic_cdk::api::msg_caller;
 use ic_cdk::{query, update};
 
@@ -63,7 +64,12 @@ pub fn delete_search_index() -> bool {
     let caller = msg_caller();
     let _ = assert_not_anonymous(&caller);
 
-    SEARCH_INDICES.with(|indices| indices.borrow_mut().remove(&caller).is_some())
+    delete_search_index_of(caller)
+}
+
+/// Delete the search index of `user`, returning whether one existed
+pub fn delete_search_index_of(user: Principal) -> bool {
+    SEARCH_INDICES.with(|indices| indices.borrow_mut().remove(&user).is_some())
 }
 
 /// Check if the caller has a search index
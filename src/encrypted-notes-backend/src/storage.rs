@@ -4,7 +4,12 @@ use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemor
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell};
 use std::cell::RefCell;
 
-use crate::types::{Nft, NftId, Note, NoteId, SearchIndex, UserProfile};
+use crate::types::{
+    Attachment, AttachmentId, AuditEntry, ChangeEntry, Comment, CommentId, EditLease, Folder,
+    FolderId, Group, GroupId, ImportSession, InviteId, Nft, NftId, Note, NoteId, NoteInvite,
+    NoteMetadata, NoteOp, NoteRevision, NoteTransfer, PendingShare, RevisionPolicy, SearchIndex,
+    UploadId, UploadSession, UserProfile, Workspace, WorkspaceId, WorkspaceRole,
+};
 
 pub type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -41,6 +46,16 @@ thread_local! {
         RefCell::new(StableBTreeMap::init(
             MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(2)))
     ));
+    // Shares waiting for the recipient to accept, keyed by (recipient, note), see inbox.rs
+    pub static SHARE_INBOX: RefCell<StableBTreeMap<(Principal, NoteId), PendingShare, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(33)))
+    ));
+    // Principals a user refuses shares from, keyed by (user, blocked)
+    pub static BLOCKLIST: RefCell<StableBTreeMap<(Principal, Principal), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(34)))
+    ));
 
     pub static SEARCH_INDICES: RefCell<StableBTreeMap<Principal, SearchIndex, Memory>> =
         RefCell::new(StableBTreeMap::init(
@@ -51,6 +66,237 @@ thread_local! {
             MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(5)))
     ));
 
+    // Archived note ciphertexts keyed by (note, revision number)
+    pub static NOTE_REVISIONS: RefCell<StableBTreeMap<(NoteId, u64), NoteRevision, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(6)))
+    ));
+
+    pub static REVISION_POLICIES: RefCell<StableBTreeMap<NoteId, RevisionPolicy, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(7)))
+    ));
+
+    // Secondary indexes over NOTES, see index.rs
+    pub static OWNER_INDEX: RefCell<StableBTreeMap<(Principal, NoteId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(8)))
+    ));
+
+    pub static GRANTEE_INDEX: RefCell<StableBTreeMap<(Principal, NoteId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(9)))
+    ));
+
+    // Trashed notes keyed by (owner, note), valued by trash timestamp
+    pub static TRASHED_NOTES: RefCell<StableBTreeMap<(Principal, NoteId), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(10)))
+    ));
+
+    // How long trashed notes are kept before purging (default: 30 days)
+    pub static TRASH_RETENTION_NANOS: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(11))),
+            30 * 24 * 60 * 60 * 1_000_000_000
+        ).unwrap()
+    );
+
+    // Content chunks of large notes keyed by (note, chunk index), see chunks.rs
+    pub static NOTE_CHUNKS: RefCell<StableBTreeMap<(NoteId, u32), String, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(12)))
+    ));
+
+    pub static UPLOAD_SESSIONS: RefCell<StableBTreeMap<UploadId, UploadSession, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(13)))
+    ));
+
+    // Chunks staged by in-progress uploads keyed by (upload, chunk index)
+    pub static UPLOAD_CHUNKS: RefCell<StableBTreeMap<(UploadId, u32), String, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(14)))
+    ));
+
+    // Encrypted note attachments keyed by (note, attachment)
+    pub static ATTACHMENTS: RefCell<StableBTreeMap<(NoteId, AttachmentId), Attachment, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(15)))
+    ));
+
+    // Pending share expiries ordered by time: (expires_at, note, grantee)
+    pub static SHARE_EXPIRY_QUEUE: RefCell<StableBTreeMap<(u64, NoteId, Principal), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(16)))
+    ));
+    // Ownership transfers keyed by (note, sequence number), see transfer.rs
+    pub static NOTE_TRANSFERS: RefCell<StableBTreeMap<(NoteId, u64), NoteTransfer, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(17)))
+    ));
+    // Pending transfers keyed by (recipient, note)
+    pub static INCOMING_TRANSFERS: RefCell<StableBTreeMap<(Principal, NoteId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(18)))
+    ));
+    // Note content re-encrypted for the recipient of a pending transfer
+    pub static TRANSFER_STAGED_CONTENT: RefCell<StableBTreeMap<NoteId, String, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(19)))
+    ));
+    // Attachment data re-encrypted for the recipient, keyed by (note, attachment)
+    pub static TRANSFER_STAGED_ATTACHMENTS: RefCell<StableBTreeMap<(NoteId, AttachmentId), Vec<u8>, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(20)))
+    ));
+    // Per-note access log keyed by (note, sequence number), see audit.rs
+    pub static AUDIT_LOG: RefCell<StableBTreeMap<(NoteId, u64), AuditEntry, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(21)))
+    ));
+    // Maximum number of audit entries kept per note (default: 1000)
+    pub static AUDIT_LOG_CAP: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(22))),
+            1000
+        ).unwrap()
+    );
+    // Sharing groups, see groups.rs
+    pub static GROUPS: RefCell<StableBTreeMap<GroupId, Group, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(23)))
+    ));
+    // Group membership keyed by (member, group)
+    pub static GROUP_MEMBERS: RefCell<StableBTreeMap<(Principal, GroupId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(24)))
+    ));
+    // Groups keyed by (owner, group)
+    pub static GROUP_OWNERS: RefCell<StableBTreeMap<(Principal, GroupId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(25)))
+    ));
+    // Notes shared with a group keyed by (group, note)
+    pub static GROUP_NOTES: RefCell<StableBTreeMap<(GroupId, NoteId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(26)))
+    ));
+    // Team workspaces, see workspaces.rs
+    pub static WORKSPACES: RefCell<StableBTreeMap<WorkspaceId, Workspace, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(27)))
+    ));
+    // Workspace roles keyed by (member, workspace)
+    pub static WORKSPACE_MEMBERS: RefCell<StableBTreeMap<(Principal, WorkspaceId), WorkspaceRole, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(28)))
+    ));
+    // Notes owned by a workspace keyed by (workspace, note)
+    pub static WORKSPACE_NOTES: RefCell<StableBTreeMap<(WorkspaceId, NoteId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(29)))
+    ));
+    // Note comments keyed by (note, comment), see comments.rs
+    pub static NOTE_COMMENTS: RefCell<StableBTreeMap<(NoteId, CommentId), Comment, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(30)))
+    ));
+    // Note invites keyed by (note, invite), see invites.rs
+    pub static NOTE_INVITES: RefCell<StableBTreeMap<(NoteId, InviteId), NoteInvite, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(31)))
+    ));
+    // Outstanding invites by SHA-256 hash of their secret
+    pub static INVITE_TOKENS: RefCell<StableBTreeMap<[u8; 32], (NoteId, InviteId), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(32)))
+    ));
+
+    // Folders by ID, see folders.rs
+    pub static FOLDERS: RefCell<StableBTreeMap<FolderId, Folder, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(35)))
+    ));
+    // Folders keyed by (owner, folder)
+    pub static OWNER_FOLDERS: RefCell<StableBTreeMap<(Principal, FolderId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(36)))
+    ));
+    // Nested folders keyed by (parent, child)
+    pub static FOLDER_CHILDREN: RefCell<StableBTreeMap<(FolderId, FolderId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(37)))
+    ));
+    // Notes filed in a folder keyed by (folder, note)
+    pub static FOLDER_NOTES: RefCell<StableBTreeMap<(FolderId, NoteId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(38)))
+    ));
+    // Folders shared with a principal keyed by (grantee, folder)
+    pub static FOLDER_GRANTEES: RefCell<StableBTreeMap<(Principal, FolderId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(39)))
+    ));
+
+    // Encrypted metadata sidecars by note, see metadata.rs
+    pub static NOTE_METADATA: RefCell<StableBTreeMap<NoteId, NoteMetadata, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(40)))
+    ));
+
+    // Global changefeed keyed by sequence, see changes.rs
+    pub static CHANGE_LOG: RefCell<StableBTreeMap<u64, ChangeEntry, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(41)))
+    ));
+
+    // CRDT operations of op-log notes keyed by (note, log position), see oplog.rs
+    pub static NOTE_OPS: RefCell<StableBTreeMap<(NoteId, u128), NoteOp, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(42)))
+    ));
+
+    // Active edit leases keyed by note, see leases.rs
+    pub static EDIT_LEASES: RefCell<StableBTreeMap<NoteId, EditLease, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(43)))
+    ));
+
+    // Account imports in progress and their note ID remapping, see archive.rs
+    pub static IMPORT_SESSIONS: RefCell<StableBTreeMap<Principal, ImportSession, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(44)))
+    ));
+    pub static IMPORT_NOTE_IDS: RefCell<StableBTreeMap<(Principal, NoteId), NoteId, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(45)))
+    ));
+
+    // Pending account deletions and their confirmation deadline, see account.rs
+    pub static ACCOUNT_DELETIONS: RefCell<StableBTreeMap<Principal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(46)))
+    ));
+
+    // Trashed notes ordered by the time they were trashed: (trashed_at, note, owner)
+    pub static TRASH_QUEUE: RefCell<StableBTreeMap<(u64, NoteId, Principal), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(47)))
+    ));
+
+    // Outstanding invites ordered by expiry: (expires_at, note, invite), see invites.rs
+    pub static INVITE_EXPIRY_QUEUE: RefCell<StableBTreeMap<(u64, NoteId, InviteId), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(48)))
+    ));
+
+    // Pending share offers keyed by note, the reverse of SHARE_INBOX: (note, recipient)
+    pub static NOTE_OFFERS: RefCell<StableBTreeMap<(NoteId, Principal), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEM_MANAGER.with_borrow(|m| m.get(MemoryId::new(49)))
+    ));
+
     static LEDGER_ID: RefCell<Option<Principal>> = RefCell::new(None);
 
 }
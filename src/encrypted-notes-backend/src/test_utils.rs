@@ -0,0 +1,41 @@
+// Unit Test Fixtures
+// src/encrypted-notes-backend/src/test_utils.rs
+//
+// Builders shared by the in-file unit tests. Fixtures start from empty
+// defaults; tests override the fields they exercise with struct update syntax.
+
+use candid::Principal;
+
+use crate::types::{Note, NoteId};
+
+/// A distinct, non-anonymous principal per `byte`
+pub fn principal(byte: u8) -> Principal {
+    Principal::from_slice(&[byte; 10])
+}
+
+/// A live, unshared note with every other field at its default
+pub fn note(id: NoteId, owner: Principal) -> Note {
+    Note {
+        id,
+        owner,
+        encrypted: String::new(),
+        shared_read: vec![],
+        shared_edit: vec![],
+        version: 0,
+        created_at: 0,
+        updated_at: 0,
+        last_editor: owner,
+        trashed_at: None,
+        chunk_count: 0,
+        share_expirations: vec![],
+        key_epoch: 0,
+        content_key_epoch: 0,
+        shared_read_groups: vec![],
+        shared_edit_groups: vec![],
+        workspace_id: None,
+        shared_comment: vec![],
+        shared_comment_groups: vec![],
+        folder_id: None,
+        op_log_clock: None,
+    }
+}
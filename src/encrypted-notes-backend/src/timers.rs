@@ -0,0 +1,27 @@
+// Background Jobs Module
+// src/encrypted-notes-backend/src/timers.rs
+//
+// Timers do not survive upgrades, so `start_timers` is called from both
+// `init` and `post_upgrade`.
+
+use std::time::Duration;
+
+/// How often expired trash is purged
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How often abandoned chunked uploads are garbage-collected
+const UPLOAD_GC_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// How often expired share grants are removed from notes
+const SHARE_SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// How often the secrets of expired invites are dropped
+const INVITE_GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How often lapsed edit leases are cleared
+const LEASE_GC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Register all recurring background jobs
+pub fn start_timers() {
+    ic_cdk_timers::set_timer_interval(TRASH_PURGE_INTERVAL, crate::trash::purge_expired_trash);
+    ic_cdk_timers::set_timer_interval(UPLOAD_GC_INTERVAL, crate::chunks::collect_abandoned_uploads);
+    ic_cdk_timers::set_timer_interval(SHARE_SWEEP_INTERVAL, crate::note::sweep_expired_shares);
+    ic_cdk_timers::set_timer_interval(INVITE_GC_INTERVAL, crate::invites::forget_expired_invites);
+    ic_cdk_timers::set_timer_interval(LEASE_GC_INTERVAL, crate::leases::clear_expired_leases);
+}
@@ -0,0 +1,386 @@
+// Note Ownership Transfer Module
+// src/encrypted-notes-backend/src/transfer.rs
+//
+// Hands a note to another principal outside the NFT marketplace. The vetKD
+// derivation input contains `note.owner`, so the handover re-keys the note:
+//
+// 1. The owner offers the note with `offer_note_transfer`.
+// 2. The owner fetches the recipient's key with
+//    `encrypted_symmetric_key_for_transfer`, re-encrypts the content and every
+//    attachment, and stages them with `stage_transfer_content` and
+//    `stage_transfer_attachment`.
+// 3. The recipient calls `accept_note_transfer`, which swaps in the staged
+//    ciphertext and the new owner in a single message.
+//
+// Either party can cancel a pending offer. Grants, invites, archived
+// revisions and comments do not survive the handover, as they are tied to
+// the previous owner's key, and neither do the audit log and edit lease.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::attachments::{attachment_ids, MAX_ATTACHMENT_SIZE};
+use crate::audit::clear_audit_log;
+use crate::changes::record_note_change;
+use crate::comments::clear_comments;
+use crate::helpers::{assert_not_anonymous, get_max_note_size};
+use crate::index::{index_note, unindex_note};
+use crate::invites::clear_invites;
+use crate::leases::clear_edit_lease;
+use crate::metadata::clear_note_metadata;
+use crate::note::{clear_revisions, derive_encrypted_key, key_derivation_input};
+use crate::oplog::has_pending_ops;
+use crate::storage::{
+    ATTACHMENTS, INCOMING_TRANSFERS, NFTS, NOTES, NOTE_TRANSFERS, TRANSFER_STAGED_ATTACHMENTS,
+    TRANSFER_STAGED_CONTENT,
+};
+use crate::types::{AttachmentId, ChangeKind, Note, NoteId, NoteTransfer};
+
+/// Offer a note to another principal
+/// Only the owner can offer; a note can have one pending transfer at a time
+#[update]
+pub fn offer_note_transfer(note_id: NoteId, to: Principal) {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let note = transferable_note(note_id);
+    if note.owner != caller {
+        ic_cdk::trap("Only owner can transfer");
+    }
+    if to == caller || to == Principal::anonymous() {
+        ic_cdk::trap("Invalid transfer recipient");
+    }
+    if pending_transfer(note_id).is_some() {
+        ic_cdk::trap("Note already has a pending transfer");
+    }
+
+    let seq = NOTE_TRANSFERS.with_borrow(|transfers| {
+        transfers
+            .keys_range(transfer_range(note_id))
+            .next_back()
+            .map_or(0, |(_, seq)| seq + 1)
+    });
+    let transfer = NoteTransfer {
+        note_id,
+        from: caller,
+        to,
+        key_epoch: note.key_epoch + 1,
+        offered_at: ic_cdk::api::time(),
+        staged_at: None,
+        staged_version: None,
+        accepted_at: None,
+        cancelled_at: None,
+        cancelled_by: None,
+    };
+
+    NOTE_TRANSFERS.with_borrow_mut(|transfers| transfers.insert((note_id, seq), transfer));
+    INCOMING_TRANSFERS.with_borrow_mut(|incoming| incoming.insert((to, note_id), ()));
+}
+
+/// Get the recipient's key for a pending transfer, encrypted to `transport_public_key`
+/// Only the offering owner can request it, to re-encrypt the note before the handover
+#[update]
+pub async fn encrypted_symmetric_key_for_transfer(
+    note_id: NoteId,
+    transport_public_key: Vec<u8>,
+) -> String {
+    let caller = msg_caller();
+    let (_, transfer) = offered_transfer(note_id, &caller);
+
+    let input = key_derivation_input(note_id, &transfer.to, transfer.key_epoch);
+    derive_encrypted_key(input, transport_public_key).await
+}
+
+/// Stage the note content re-encrypted under the recipient's key
+/// Must be called again if the note is edited before the transfer is accepted
+#[update]
+pub fn stage_transfer_content(note_id: NoteId, encrypted: String) {
+    let caller = msg_caller();
+    let (key, mut transfer) = offered_transfer(note_id, &caller);
+    let note = transferable_note(note_id);
+
+    let max_size = get_max_note_size();
+    if encrypted.len() > max_size {
+        ic_cdk::trap(format!(
+            "Note too large: {} bytes exceeds limit of {} bytes",
+            encrypted.len(),
+            max_size
+        ));
+    }
+
+    TRANSFER_STAGED_CONTENT.with_borrow_mut(|staged| staged.insert(note_id, encrypted));
+
+    transfer.staged_at = Some(ic_cdk::api::time());
+    transfer.staged_version = Some(note.version);
+    NOTE_TRANSFERS.with_borrow_mut(|transfers| transfers.insert(key, transfer));
+}
+
+/// Stage one attachment re-encrypted under the recipient's key
+#[update]
+pub fn stage_transfer_attachment(note_id: NoteId, attachment_id: AttachmentId, data: Vec<u8>) {
+    let caller = msg_caller();
+    offered_transfer(note_id, &caller);
+
+    let exists = ATTACHMENTS.with_borrow(|store| store.contains_key(&(note_id, attachment_id)));
+    if !exists {
+        ic_cdk::trap("Attachment not found");
+    }
+    if data.len() > MAX_ATTACHMENT_SIZE {
+        ic_cdk::trap(format!(
+            "Attachment too large: {} bytes exceeds limit of {} bytes",
+            data.len(),
+            MAX_ATTACHMENT_SIZE
+        ));
+    }
+
+    TRANSFER_STAGED_ATTACHMENTS
+        .with_borrow_mut(|staged| staged.insert((note_id, attachment_id), data));
+}
+
+/// Accept a pending transfer and become the note's owner
+/// Requires the previous owner to have staged re-encrypted content for the
+/// current note version and every attachment
+/// The metadata sidecar is dropped, as it is encrypted under the previous owner's key
+#[update]
+pub fn accept_note_transfer(note_id: NoteId) {
+    let caller = msg_caller();
+    let (key, mut transfer) = pending_transfer(note_id)
+        .unwrap_or_else(|| ic_cdk::trap("No pending transfer for this note"));
+    if transfer.to != caller {
+        ic_cdk::trap("Transfer is not addressed to you");
+    }
+
+    let mut note = transferable_note(note_id);
+    if note.owner != transfer.from {
+        ic_cdk::trap("Note changed owner since the transfer was offered");
+    }
+    if transfer.staged_version != Some(note.version) {
+        ic_cdk::trap("Note content has not been re-encrypted for the current version");
+    }
+    // Pending operations are encrypted under the sender's key and cannot be staged
+    if has_pending_ops(note_id) {
+        ic_cdk::trap("The note's operation log must be compacted before accepting");
+    }
+
+    let staged_content = TRANSFER_STAGED_CONTENT
+        .with_borrow(|staged| staged.get(&note_id))
+        .unwrap_or_else(|| ic_cdk::trap("Note content has not been re-encrypted"));
+    let attachments = attachment_ids(note_id);
+    let all_staged = TRANSFER_STAGED_ATTACHMENTS.with_borrow(|staged| {
+        attachments
+            .iter()
+            .all(|attachment_id| staged.contains_key(&(note_id, *attachment_id)))
+    });
+    if !all_staged {
+        ic_cdk::trap("Every attachment must be re-encrypted before accepting");
+    }
+
+    let now = ic_cdk::api::time();
+
+    let before = note.clone();
+    unindex_note(&note);
+    note.owner = caller;
+    note.shared_read.clear();
+    note.shared_edit.clear();
+    note.share_expirations.clear();
+    note.shared_read_groups.clear();
+    note.shared_edit_groups.clear();
+    note.shared_comment.clear();
+    note.shared_comment_groups.clear();
+    note.folder_id = None;
+    // The sender derived the recipient's key for `transfer.key_epoch` to stage
+    // the content, so the recipient writes under the epoch after it
+    note.key_epoch = transfer.key_epoch + 1;
+    note.encrypted = staged_content;
+    note.content_key_epoch = transfer.key_epoch;
+    note.version += 1;
+    note.updated_at = now;
+    note.last_editor = transfer.from;
+    index_note(&note);
+    record_note_change(Some(&before), &note, ChangeKind::OwnerChanged);
+    NOTES.with_borrow_mut(|store| store.insert(note_id, note));
+    clear_note_metadata(note_id);
+
+    ATTACHMENTS.with_borrow_mut(|store| {
+        TRANSFER_STAGED_ATTACHMENTS.with_borrow(|staged| {
+            for attachment_id in attachments {
+                if let (Some(mut attachment), Some(data)) = (
+                    store.get(&(note_id, attachment_id)),
+                    staged.get(&(note_id, attachment_id)),
+                ) {
+                    attachment.size = data.len() as u64;
+                    attachment.data = data;
+                    attachment.key_epoch = Some(transfer.key_epoch);
+                    store.insert((note_id, attachment_id), attachment);
+                }
+            }
+        });
+    });
+    clear_revisions(note_id);
+    clear_comments(note_id);
+    clear_audit_log(note_id);
+    clear_invites(note_id);
+    clear_edit_lease(note_id);
+    clear_staging(note_id);
+
+    transfer.accepted_at = Some(now);
+    NOTE_TRANSFERS.with_borrow_mut(|transfers| transfers.insert(key, transfer));
+    INCOMING_TRANSFERS.with_borrow_mut(|incoming| incoming.remove(&(caller, note_id)));
+}
+
+/// Cancel a pending transfer
+/// The offering owner can withdraw it and the recipient can decline it
+#[update]
+pub fn cancel_note_transfer(note_id: NoteId) {
+    let caller = msg_caller();
+    let (key, transfer) = pending_transfer(note_id)
+        .unwrap_or_else(|| ic_cdk::trap("No pending transfer for this note"));
+    if transfer.from != caller && transfer.to != caller {
+        ic_cdk::trap("Not authorized to cancel this transfer");
+    }
+
+    cancel_transfer(key, transfer, caller);
+}
+
+/// Decline every pending transfer offered to `user`, returning their notes
+pub fn decline_incoming_transfers(user: Principal) -> Vec<NoteId> {
+    let note_ids: Vec<NoteId> = INCOMING_TRANSFERS.with_borrow(|incoming| {
+        incoming
+            .keys_range((user, 0)..=(user, NoteId::MAX))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    });
+
+    for note_id in &note_ids {
+        match pending_transfer(*note_id) {
+            Some((key, transfer)) if transfer.to == user => cancel_transfer(key, transfer, user),
+            _ => {
+                INCOMING_TRANSFERS.with_borrow_mut(|incoming| incoming.remove(&(user, *note_id)));
+            }
+        }
+    }
+    note_ids
+}
+
+/// Get the pending transfers offered to the caller
+#[query]
+pub fn get_incoming_transfers() -> Vec<NoteTransfer> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let note_ids: Vec<NoteId> = INCOMING_TRANSFERS.with_borrow(|incoming| {
+        incoming
+            .keys_range((caller, 0)..=(caller, NoteId::MAX))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    });
+
+    note_ids
+        .into_iter()
+        .filter_map(|note_id| pending_transfer(note_id).map(|(_, transfer)| transfer))
+        .collect()
+}
+
+/// Get the transfer history of a note, oldest first
+/// Visible to the current owner and to both parties of each transfer
+#[query]
+pub fn get_note_transfers(note_id: NoteId) -> Vec<NoteTransfer> {
+    let caller = msg_caller();
+    let is_owner = NOTES.with_borrow(|store| {
+        store
+            .get(&note_id)
+            .map(|note| note.owner == caller)
+            .unwrap_or(false)
+    });
+
+    NOTE_TRANSFERS.with_borrow(|transfers| {
+        transfers
+            .range(transfer_range(note_id))
+            .map(|(_, transfer)| transfer)
+            .filter(|transfer| is_owner || transfer.from == caller || transfer.to == caller)
+            .collect()
+    })
+}
+
+/// Drop every transfer record and staged ciphertext of a note
+pub fn clear_transfers(note_id: NoteId) {
+    if let Some((_, transfer)) = pending_transfer(note_id) {
+        INCOMING_TRANSFERS.with_borrow_mut(|incoming| incoming.remove(&(transfer.to, note_id)));
+    }
+    clear_staging(note_id);
+    NOTE_TRANSFERS.with_borrow_mut(|transfers| {
+        let keys: Vec<(NoteId, u64)> = transfers.keys_range(transfer_range(note_id)).collect();
+        for key in keys {
+            transfers.remove(&key);
+        }
+    });
+}
+
+fn cancel_transfer(key: (NoteId, u64), mut transfer: NoteTransfer, by: Principal) {
+    let note_id = transfer.note_id;
+    transfer.cancelled_at = Some(ic_cdk::api::time());
+    transfer.cancelled_by = Some(by);
+    INCOMING_TRANSFERS.with_borrow_mut(|incoming| incoming.remove(&(transfer.to, note_id)));
+    NOTE_TRANSFERS.with_borrow_mut(|transfers| transfers.insert(key, transfer));
+    clear_staging(note_id);
+}
+
+fn transfer_range(note_id: NoteId) -> std::ops::RangeInclusive<(NoteId, u64)> {
+    (note_id, 0)..=(note_id, u64::MAX)
+}
+
+/// The latest transfer of a note, if it is still pending
+fn pending_transfer(note_id: NoteId) -> Option<((NoteId, u64), NoteTransfer)> {
+    NOTE_TRANSFERS.with_borrow(|transfers| {
+        transfers
+            .range(transfer_range(note_id))
+            .next_back()
+            .filter(|(_, transfer)| transfer.is_pending())
+    })
+}
+
+/// The pending transfer of a note, trapping unless `caller` offered it
+fn offered_transfer(note_id: NoteId, caller: &Principal) -> ((NoteId, u64), NoteTransfer) {
+    match pending_transfer(note_id) {
+        Some((key, transfer)) if &transfer.from == caller => (key, transfer),
+        Some(_) => ic_cdk::trap("Only the offering owner can prepare this transfer"),
+        None => ic_cdk::trap("No pending transfer for this note"),
+    }
+}
+
+/// Load a note, trapping if it cannot change hands
+fn transferable_note(note_id: NoteId) -> Note {
+    let note = NOTES
+        .with_borrow(|store| store.get(&note_id))
+        .unwrap_or_else(|| ic_cdk::trap("Note not found"));
+
+    if note.is_trashed() {
+        ic_cdk::trap("Cannot transfer a trashed note");
+    }
+    if note.workspace_id.is_some() {
+        ic_cdk::trap("Workspace notes are reassigned by workspace admins");
+    }
+    if note.is_chunked() {
+        ic_cdk::trap("Chunked notes must be saved inline before they can be transferred");
+    }
+    let has_minted_nft =
+        NFTS.with_borrow(|store| store.iter().any(|(_, nft)| nft.note_id == note_id));
+    if has_minted_nft {
+        ic_cdk::trap("Notes minted to an NFT change owner through the marketplace");
+    }
+
+    note
+}
+
+fn clear_staging(note_id: NoteId) {
+    TRANSFER_STAGED_CONTENT.with_borrow_mut(|staged| staged.remove(&note_id));
+    TRANSFER_STAGED_ATTACHMENTS.with_borrow_mut(|staged| {
+        let keys: Vec<(NoteId, AttachmentId)> = staged
+            .keys_range((note_id, 0)..=(note_id, AttachmentId::MAX))
+            .collect();
+        for key in keys {
+            staged.remove(&key);
+        }
+    });
+}
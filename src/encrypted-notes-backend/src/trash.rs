@@ -0,0 +1,277 @@
+// Trash Bin Module
+// src/encrypted-notes-backend/src/trash.rs
+//
+// `delete_note` only moves a note into the trash. Trashed notes are dropped
+// from the listings and readable by their owner alone until they are
+// restored or purged, either explicitly or by the recurring purge job. They
+// keep their sharing index entries so revocations made meanwhile reach them.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+use std::ops::Bound;
+
+use crate::attachments::clear_attachments;
+use crate::audit::clear_audit_log;
+use crate::changes::{record_note_change, record_note_deleted};
+use crate::chunks::clear_note_chunks;
+use crate::comments::clear_comments;
+use crate::folders::is_folder_of;
+use crate::groups::drop_missing_groups;
+use crate::helpers::{assert_not_anonymous, now, paginate, parse_cursor};
+use crate::inbox::clear_pending_shares;
+use crate::index::{index_note, unindex_note, unindex_owner};
+use crate::invites::clear_invites;
+use crate::leases::clear_edit_lease;
+use crate::metadata::clear_note_metadata;
+use crate::note::clear_revisions;
+use crate::oplog::clear_note_ops;
+use crate::storage::{NOTES, REVISION_POLICIES, TRASHED_NOTES, TRASH_QUEUE, TRASH_RETENTION_NANOS};
+use crate::transfer::clear_transfers;
+use crate::types::{ChangeKind, Note, NoteId, Page, PageRequest};
+
+/// Most notes erased by a single run of the purge job
+const MAX_PURGED_PER_RUN: usize = 100;
+
+/// Move a note into its owner's trash
+pub fn move_to_trash(note: &mut Note) {
+    let now = now();
+    let before = note.clone();
+
+    unindex_owner(note);
+    note.trashed_at = Some(now);
+    mark_trashed(note.owner, note.id, now);
+    record_note_change(Some(&before), note, ChangeKind::Trashed);
+}
+
+/// List one page of the caller's trashed notes, ordered by note ID
+#[query]
+pub fn list_trash(request: PageRequest) -> Page<Note> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let start = match parse_cursor::<NoteId>(&request) {
+        Some(cursor) => Bound::Excluded((caller, cursor)),
+        None => Bound::Included((caller, 0)),
+    };
+
+    TRASHED_NOTES.with_borrow(|trash| {
+        NOTES.with_borrow(|store| {
+            let entries = trash
+                .keys_range((start, Bound::Included((caller, NoteId::MAX))))
+                .filter_map(|(_, note_id)| store.get(&note_id))
+                .map(|note| (note.id, note));
+            paginate(entries, &request)
+        })
+    })
+}
+
+/// Restore a trashed note, making it visible to its grantees again
+/// Only the owner can restore
+#[update]
+pub fn restore_note(note_id: NoteId) {
+    let caller = msg_caller();
+
+    NOTES.with_borrow_mut(|store| {
+        if let Some(mut note) = store.get(&note_id) {
+            if !note.is_managed_by(&caller) {
+                ic_cdk::trap("Only owner can restore");
+            }
+            if !note.is_trashed() {
+                ic_cdk::trap("Note is not in the trash");
+            }
+
+            take_out_of_trash(&mut note);
+            store.insert(note_id, note);
+        } else {
+            ic_cdk::trap("Note not found");
+        }
+    });
+}
+
+fn take_out_of_trash(note: &mut Note) {
+    unmark_trashed(note.owner, note.id);
+    note.trashed_at = None;
+    // The folder may have been deleted while the note was in the trash
+    if note
+        .folder_id
+        .is_some_and(|folder_id| !is_folder_of(folder_id, &note.owner))
+    {
+        note.folder_id = None;
+    }
+    // Groups deleted before group removal covered the trash may still be listed
+    drop_missing_groups(note);
+    index_note(note);
+    record_note_change(None, note, ChangeKind::Restored);
+}
+
+/// Permanently delete a trashed note right away
+/// Only the owner can purge; workspace admins can purge any workspace note
+#[update]
+pub fn purge_note(note_id: NoteId) {
+    let caller = msg_caller();
+
+    let owner = match NOTES.with_borrow(|store| store.get(&note_id)) {
+        Some(note) if !note.is_managed_by(&caller) => ic_cdk::trap("Only owner can purge"),
+        Some(note) if note.is_trashed() => note.owner,
+        _ => ic_cdk::trap("Note is not in the trash"),
+    };
+
+    erase_note(owner, note_id);
+}
+
+/// Permanently delete every note in the caller's trash
+/// Returns the number of purged notes
+#[update]
+pub fn empty_trash() -> u64 {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    let note_ids: Vec<NoteId> = TRASHED_NOTES.with_borrow(|trash| {
+        trash
+            .keys_range((caller, 0)..=(caller, NoteId::MAX))
+            .map(|(_, note_id)| note_id)
+            .collect()
+    });
+
+    for note_id in &note_ids {
+        erase_note(caller, *note_id);
+    }
+
+    note_ids.len() as u64
+}
+
+/// Set how long trashed notes are kept before the purge job removes them
+/// Only callable by controllers
+#[update]
+pub fn set_trash_retention_nanos(retention_nanos: u64) -> Result<(), String> {
+    let caller = msg_caller();
+
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can set trash retention".to_string());
+    }
+
+    TRASH_RETENTION_NANOS.with_borrow_mut(|cell| {
+        cell.set(retention_nanos)
+            .map_err(|_| "Failed to update trash retention".to_string())
+            .map(|_| ())
+    })
+}
+
+/// Get how long trashed notes are kept before being purged
+#[query]
+pub fn get_trash_retention_nanos() -> u64 {
+    TRASH_RETENTION_NANOS.with_borrow(|cell| *cell.get())
+}
+
+/// Purge the oldest trashed notes past the retention window, at most
+/// `MAX_PURGED_PER_RUN` per call
+/// Runs periodically from the timer registered in timers.rs
+pub fn purge_expired_trash() {
+    let cutoff = ic_cdk::api::time().saturating_sub(get_trash_retention_nanos());
+
+    let expired: Vec<(u64, NoteId, Principal)> = TRASH_QUEUE.with_borrow(|queue| {
+        queue
+            .keys()
+            .take_while(|(trashed_at, _, _)| *trashed_at < cutoff)
+            .take(MAX_PURGED_PER_RUN)
+            .collect()
+    });
+
+    for key @ (_, note_id, owner) in expired {
+        TRASH_QUEUE.with_borrow_mut(|queue| queue.remove(&key));
+        erase_note(owner, note_id);
+    }
+}
+
+/// Record that `owner` trashed a note at `trashed_at`
+pub fn mark_trashed(owner: Principal, note_id: NoteId, trashed_at: u64) {
+    TRASHED_NOTES.with_borrow_mut(|trash| trash.insert((owner, note_id), trashed_at));
+    TRASH_QUEUE.with_borrow_mut(|queue| queue.insert((trashed_at, note_id, owner), ()));
+}
+
+/// Take a note out of the trash of `owner`
+pub fn unmark_trashed(owner: Principal, note_id: NoteId) {
+    if let Some(trashed_at) = TRASHED_NOTES.with_borrow_mut(|trash| trash.remove(&(owner, note_id)))
+    {
+        TRASH_QUEUE.with_borrow_mut(|queue| queue.remove(&(trashed_at, note_id, owner)));
+    }
+}
+
+/// Populate the purge queue from the trash if it has never been built
+/// Called after upgrades so canisters that predate the queue migrate
+pub fn rebuild_trash_queue() {
+    let queued = TRASH_QUEUE.with_borrow(|queue| !queue.is_empty());
+    if queued {
+        return;
+    }
+
+    TRASHED_NOTES.with_borrow(|trash| {
+        TRASH_QUEUE.with_borrow_mut(|queue| {
+            for ((owner, note_id), trashed_at) in trash.iter() {
+                queue.insert((trashed_at, note_id, owner), ());
+            }
+        })
+    });
+}
+
+/// Permanently delete a note and everything attached to it
+pub fn erase_note(owner: Principal, note_id: NoteId) {
+    unmark_trashed(owner, note_id);
+    if let Some(note) = NOTES.with_borrow_mut(|store| store.remove(&note_id)) {
+        unindex_note(&note);
+        record_note_deleted(&note);
+    }
+    clear_revisions(note_id);
+    clear_note_chunks(note_id);
+    clear_attachments(note_id);
+    clear_transfers(note_id);
+    clear_audit_log(note_id);
+    clear_comments(note_id);
+    clear_invites(note_id);
+    clear_pending_shares(note_id);
+    clear_note_metadata(note_id);
+    clear_note_ops(note_id);
+    clear_edit_lease(note_id);
+    REVISION_POLICIES.with_borrow_mut(|policies| policies.remove(&note_id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::granted_note_ids;
+    use crate::note::revoke_user_grants;
+    use crate::test_utils::{note, principal};
+
+    #[test]
+    fn test_purge_queue_follows_the_trash() {
+        let (alice, bob) = (principal(1), principal(2));
+        mark_trashed(alice, 1, 30);
+        mark_trashed(bob, 2, 10);
+        mark_trashed(alice, 3, 20);
+
+        unmark_trashed(alice, 3);
+
+        let queued: Vec<(u64, NoteId, Principal)> =
+            TRASH_QUEUE.with_borrow(|queue| queue.keys().collect());
+        assert_eq!(queued, vec![(10, 2, bob), (30, 1, alice)]);
+        assert!(TRASHED_NOTES.with_borrow(|trash| !trash.contains_key(&(alice, 3))));
+    }
+
+    #[test]
+    fn test_grants_revoked_in_the_trash_stay_revoked_on_restore() {
+        let (alice, bob) = (principal(3), principal(4));
+        let mut shared = note(4, alice);
+        shared.shared_read.push(bob);
+        index_note(&shared);
+        move_to_trash(&mut shared);
+        NOTES.with_borrow_mut(|store| store.insert(4, shared));
+
+        assert_eq!(revoke_user_grants(bob), vec![4]);
+
+        let mut restored = NOTES.with_borrow(|store| store.get(&4)).unwrap();
+        take_out_of_trash(&mut restored);
+        assert!(!restored.can_read(&bob));
+        assert!(granted_note_ids(bob).is_empty());
+    }
+}
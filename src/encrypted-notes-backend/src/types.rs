@@ -2,10 +2,21 @@ use candid::{CandidType, Decode, Deserialize, Encode, Principal};
 use ic_cdk::api::debug_print;
 use ic_stable_structures::Storable;
 use serde::Serialize;
+
+use crate::folders::folder_permission;
+use crate::groups::is_member_of_any;
+use crate::workspaces::workspace_role;
 use std::{borrow::Cow, cell::RefCell};
 
 pub type NoteId = u128;
 pub type NftId = u128;
+pub type UploadId = u128;
+pub type AttachmentId = u128;
+pub type GroupId = u128;
+pub type WorkspaceId = u128;
+pub type CommentId = u128;
+pub type InviteId = u128;
+pub type FolderId = u128;
 
 #[derive(Debug, CandidType, Deserialize, Clone)]
 pub struct Note {
@@ -15,6 +26,710 @@ pub struct Note {
     pub shared_read: Vec<Principal>,
     #[serde(default)]
     pub shared_edit: Vec<Principal>,
+    /// Incremented on every content change, used for optimistic concurrency
+    #[serde(default)]
+    pub version: u64,
+    /// Nanoseconds since epoch; 0 for notes created before timestamps existed
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub updated_at: u64,
+    /// Principal that last wrote the content (the owner for legacy notes)
+    pub last_editor: Principal,
+    /// Set while the note sits in its owner's trash
+    #[serde(default)]
+    pub trashed_at: Option<u64>,
+    /// Number of content chunks in NOTE_CHUNKS; 0 means the content is inline in `encrypted`
+    #[serde(default)]
+    pub chunk_count: u32,
+    /// Expiry of time-limited grants in `shared_read`/`shared_edit`; grants without an entry never expire
+    #[serde(default)]
+    pub share_expirations: Vec<ShareExpiration>,
+    /// Latest vetKD key epoch, bumped whenever a grant is revoked
+    #[serde(default)]
+    pub key_epoch: u64,
+    /// Key epoch the current `encrypted` content was written under
+    #[serde(default)]
+    pub content_key_epoch: u64,
+    /// Groups whose members can read the note, see groups.rs
+    #[serde(default)]
+    pub shared_read_groups: Vec<GroupId>,
+    /// Groups whose members can edit the note
+    #[serde(default)]
+    pub shared_edit_groups: Vec<GroupId>,
+    /// Workspace that owns the note, see workspaces.rs; `owner` is then the member stewarding it
+    #[serde(default)]
+    pub workspace_id: Option<WorkspaceId>,
+    /// Principals allowed to read and comment without editing, see comments.rs
+    #[serde(default)]
+    pub shared_comment: Vec<Principal>,
+    /// Groups whose members can comment on the note
+    #[serde(default)]
+    pub shared_comment_groups: Vec<GroupId>,
+    /// Folder the note is filed in, see folders.rs
+    #[serde(default)]
+    pub folder_id: Option<FolderId>,
+    /// Set in op-log mode, see oplog.rs: Lamport clock up to which operations are folded into `encrypted`
+    #[serde(default)]
+    pub op_log_clock: Option<u64>,
+}
+
+#[derive(Debug, CandidType, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SharePermission {
+    Read,
+    Edit,
+    /// Read and comment, without changing the content
+    Comment,
+}
+
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq)]
+pub struct ShareExpiration {
+    pub user: Principal,
+    pub permission: SharePermission,
+    pub expires_at: u64,
+}
+
+/// A single grant on a note as reported to its owner
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq)]
+pub struct ShareGrant {
+    pub user: Principal,
+    pub permission: SharePermission,
+    pub expires_at: Option<u64>,
+}
+
+/// Stored shape of a `Note` used when decoding from stable memory.
+/// Candid only tolerates missing record fields when they are `opt`, so every
+/// field added after the first release is optional here and defaulted in
+/// the `From` conversion below.
+#[derive(CandidType, Deserialize)]
+struct NoteRecord {
+    id: NoteId,
+    owner: Principal,
+    encrypted: String,
+    shared_read: Vec<Principal>,
+    #[serde(default)]
+    shared_edit: Option<Vec<Principal>>,
+    #[serde(default)]
+    version: Option<u64>,
+    #[serde(default)]
+    created_at: Option<u64>,
+    #[serde(default)]
+    updated_at: Option<u64>,
+    #[serde(default)]
+    last_editor: Option<Principal>,
+    #[serde(default)]
+    trashed_at: Option<u64>,
+    #[serde(default)]
+    chunk_count: Option<u32>,
+    #[serde(default)]
+    share_expirations: Option<Vec<ShareExpiration>>,
+    #[serde(default)]
+    key_epoch: Option<u64>,
+    #[serde(default)]
+    content_key_epoch: Option<u64>,
+    #[serde(default)]
+    shared_read_groups: Option<Vec<GroupId>>,
+    #[serde(default)]
+    shared_edit_groups: Option<Vec<GroupId>>,
+    #[serde(default)]
+    workspace_id: Option<WorkspaceId>,
+    #[serde(default)]
+    shared_comment: Option<Vec<Principal>>,
+    #[serde(default)]
+    shared_comment_groups: Option<Vec<GroupId>>,
+    #[serde(default)]
+    folder_id: Option<FolderId>,
+    #[serde(default)]
+    op_log_clock: Option<u64>,
+}
+
+impl From<NoteRecord> for Note {
+    fn from(record: NoteRecord) -> Self {
+        Note {
+            id: record.id,
+            owner: record.owner,
+            encrypted: record.encrypted,
+            shared_read: record.shared_read,
+            shared_edit: record.shared_edit.unwrap_or_default(),
+            version: record.version.unwrap_or_default(),
+            created_at: record.created_at.unwrap_or_default(),
+            updated_at: record.updated_at.unwrap_or_default(),
+            last_editor: record.last_editor.unwrap_or(record.owner),
+            trashed_at: record.trashed_at,
+            chunk_count: record.chunk_count.unwrap_or_default(),
+            share_expirations: record.share_expirations.unwrap_or_default(),
+            key_epoch: record.key_epoch.unwrap_or_default(),
+            content_key_epoch: record.content_key_epoch.unwrap_or_default(),
+            shared_read_groups: record.shared_read_groups.unwrap_or_default(),
+            shared_edit_groups: record.shared_edit_groups.unwrap_or_default(),
+            workspace_id: record.workspace_id,
+            shared_comment: record.shared_comment.unwrap_or_default(),
+            shared_comment_groups: record.shared_comment_groups.unwrap_or_default(),
+            folder_id: record.folder_id,
+            op_log_clock: record.op_log_clock,
+        }
+    }
+}
+
+/// Ordering for the sorted note listings
+#[derive(Debug, CandidType, Deserialize, Clone, Copy)]
+pub enum NoteOrder {
+    CreatedAsc,
+    CreatedDesc,
+    UpdatedAsc,
+    UpdatedDesc,
+}
+
+/// Typed failures of `update_note_versioned`
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub enum NoteUpdateError {
+    NotFound,
+    Unauthorized,
+    TooLarge { size: u64, max_size: u64 },
+    /// The note changed since the caller loaded it
+    VersionConflict { current_version: u64 },
+    /// The content must be re-encrypted under the note's latest key epoch
+    StaleKeyEpoch { current_key_epoch: u64 },
+    /// The note is in op-log mode and only changes through operations, see oplog.rs
+    OpLogMode,
+    /// The note is chunked and only changes through a new chunked upload, see chunks.rs
+    Chunked,
+    /// Another editor holds the note's edit lease, see leases.rs
+    Leased { holder: Principal, expires_at: u64 },
+}
+
+/// Typed failures of the username-based sharing endpoints
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq)]
+pub enum ShareError {
+    NoteNotFound,
+    NotOwner,
+    NoteTrashed,
+    ExpiryInPast,
+    InvalidUsername,
+    /// No registered user has this username
+    UnknownUsername { username: String },
+    CannotShareWithSelf,
+    TooManyUsernames { max: u32 },
+    /// The recipient has blocked the caller
+    Blocked,
+}
+
+/// Per-username outcome of `share_note_with_usernames`
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct UsernameShareResult {
+    pub username: String,
+    /// Principal the share was offered to on success
+    pub result: Result<Principal, ShareError>,
+}
+
+/// Small client-encrypted sidecar of a note (title, snippet, tags, color), see metadata.rs
+/// Encrypted under the note key of `key_epoch`, like the note itself
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NoteMetadata {
+    pub encrypted: String,
+    pub key_epoch: u64,
+    pub updated_at: u64,
+    pub updated_by: Principal,
+}
+
+/// Note listing entry carrying the metadata sidecar instead of the content
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NoteSummary {
+    pub id: NoteId,
+    pub owner: Principal,
+    pub version: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Latest key epoch of the note
+    pub key_epoch: u64,
+    pub folder_id: Option<FolderId>,
+    pub workspace_id: Option<WorkspaceId>,
+    pub metadata: Option<NoteMetadata>,
+}
+
+/// Archived ciphertext of a note, captured right before it was overwritten
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NoteRevision {
+    pub note_id: NoteId,
+    pub revision_id: u64,
+    pub encrypted: String,
+    /// Principal who wrote this ciphertext
+    pub author: Principal,
+    /// When this ciphertext was written
+    pub created_at: u64,
+    /// Key epoch the ciphertext was written under; `None` for revisions
+    /// archived before key rotation existed, which all use epoch 0
+    #[serde(default)]
+    pub key_epoch: Option<u64>,
+}
+
+/// Revision listing entry without the ciphertext payload
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NoteRevisionSummary {
+    pub revision_id: u64,
+    pub author: Principal,
+    pub created_at: u64,
+    pub size: u64,
+}
+
+/// Per-note revision retention; `None` disables the corresponding limit
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq)]
+pub struct RevisionPolicy {
+    pub max_revisions: Option<u32>,
+    pub max_age_nanos: Option<u64>,
+}
+
+/// In-progress chunked upload, see chunks.rs
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct UploadSession {
+    pub id: UploadId,
+    pub owner: Principal,
+    /// Note whose content is replaced on commit; `None` creates a new note
+    pub note_id: Option<NoteId>,
+    pub chunk_count: u32,
+    pub total_size: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Key epoch the staged chunks are encrypted under
+    #[serde(default)]
+    pub key_epoch: Option<u64>,
+}
+
+/// Client-encrypted file attached to a note, see attachments.rs
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct Attachment {
+    pub id: AttachmentId,
+    pub note_id: NoteId,
+    pub mime_type: String,
+    pub size: u64,
+    pub data: Vec<u8>,
+    pub uploaded_by: Principal,
+    pub created_at: u64,
+    /// Key epoch the data is encrypted under; `None` means epoch 0
+    #[serde(default)]
+    pub key_epoch: Option<u64>,
+}
+
+/// Attachment listing entry without the encrypted bytes
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct AttachmentInfo {
+    pub id: AttachmentId,
+    pub note_id: NoteId,
+    pub mime_type: String,
+    pub size: u64,
+    pub uploaded_by: Principal,
+    pub created_at: u64,
+    pub key_epoch: Option<u64>,
+}
+
+/// Owner-defined set of principals that notes can be shared with, see groups.rs
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct Group {
+    pub id: GroupId,
+    pub owner: Principal,
+    pub name: String,
+    pub members: Vec<Principal>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, CandidType, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WorkspaceRole {
+    /// Manages membership and has owner rights on every workspace note
+    Admin,
+    Member,
+}
+
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct WorkspaceMember {
+    pub principal: Principal,
+    pub role: WorkspaceRole,
+}
+
+/// Team entity that owns notes independently of any single member, see workspaces.rs
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct Workspace {
+    pub id: WorkspaceId,
+    pub name: String,
+    pub members: Vec<WorkspaceMember>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// Owner-defined folder of notes, see folders.rs
+/// Folders nest through `parent_id`; a grant on a folder covers every note
+/// filed in it or in any folder beneath it
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct Folder {
+    pub id: FolderId,
+    pub owner: Principal,
+    pub parent_id: Option<FolderId>,
+    /// Client-encrypted name, under the folder key of `name_key_epoch`
+    pub encrypted_name: String,
+    pub shared_read: Vec<Principal>,
+    pub shared_edit: Vec<Principal>,
+    pub shared_comment: Vec<Principal>,
+    /// Latest key epoch, rotated whenever a grant on the folder is revoked
+    pub key_epoch: u64,
+    pub name_key_epoch: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Folder {
+    pub fn grantees(&self, permission: SharePermission) -> &Vec<Principal> {
+        match permission {
+            SharePermission::Read => &self.shared_read,
+            SharePermission::Edit => &self.shared_edit,
+            SharePermission::Comment => &self.shared_comment,
+        }
+    }
+
+    pub fn grantees_mut(&mut self, permission: SharePermission) -> &mut Vec<Principal> {
+        match permission {
+            SharePermission::Read => &mut self.shared_read,
+            SharePermission::Edit => &mut self.shared_edit,
+            SharePermission::Comment => &mut self.shared_comment,
+        }
+    }
+
+    /// Strongest permission granted to `principal` on this folder itself
+    pub fn permission_of(&self, principal: &Principal) -> Option<SharePermission> {
+        [
+            SharePermission::Edit,
+            SharePermission::Comment,
+            SharePermission::Read,
+        ]
+        .into_iter()
+        .find(|permission| self.grantees(*permission).contains(principal))
+    }
+
+    pub fn is_shared(&self) -> bool {
+        !self.shared_read.is_empty()
+            || !self.shared_edit.is_empty()
+            || !self.shared_comment.is_empty()
+    }
+}
+
+/// Comment on a note, see comments.rs
+/// The body is client-encrypted under the note key of `key_epoch`
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct Comment {
+    pub id: CommentId,
+    pub note_id: NoteId,
+    /// Comment this one replies to; `None` starts a new thread
+    pub parent_id: Option<CommentId>,
+    pub author: Principal,
+    pub encrypted_body: String,
+    pub key_epoch: u64,
+    pub resolved: bool,
+    pub resolved_by: Option<Principal>,
+    /// Deleted comments keep their place in the thread with an empty body
+    pub deleted: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// Single-use invite to a note for someone whose principal is not known yet, see invites.rs
+/// Only the SHA-256 hash of the secret is stored
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NoteInvite {
+    pub id: InviteId,
+    pub note_id: NoteId,
+    pub created_by: Principal,
+    pub permission: SharePermission,
+    pub token_hash: Vec<u8>,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub redeemed_by: Option<Principal>,
+    pub redeemed_at: Option<u64>,
+    pub revoked_at: Option<u64>,
+}
+
+impl NoteInvite {
+    pub fn is_outstanding(&self, now: u64) -> bool {
+        self.redeemed_at.is_none() && self.revoked_at.is_none() && self.expires_at > now
+    }
+}
+
+/// Share offered to a user who has not accepted or declined it yet, see inbox.rs
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct PendingShare {
+    pub note_id: NoteId,
+    pub from: Principal,
+    pub permission: SharePermission,
+    /// Expiry the grant will carry once accepted
+    pub expires_at: Option<u64>,
+    pub created_at: u64,
+}
+
+/// Two-step ownership handover of a note, see transfer.rs
+/// A transfer is pending until either `accepted_at` or `cancelled_at` is set
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NoteTransfer {
+    pub note_id: NoteId,
+    pub from: Principal,
+    pub to: Principal,
+    /// Key epoch the note is re-encrypted under for the new owner
+    pub key_epoch: u64,
+    pub offered_at: u64,
+    /// When the current owner last staged re-encrypted content
+    pub staged_at: Option<u64>,
+    /// Note version the staged content was re-encrypted from
+    pub staged_version: Option<u64>,
+    pub accepted_at: Option<u64>,
+    pub cancelled_at: Option<u64>,
+    /// Principal that cancelled: the offering owner or the declining recipient
+    pub cancelled_by: Option<Principal>,
+}
+
+impl NoteTransfer {
+    pub fn is_pending(&self) -> bool {
+        self.accepted_at.is_none() && self.cancelled_at.is_none()
+    }
+}
+
+/// Kind of access recorded in a note's audit log, see audit.rs
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq)]
+pub enum AuditAction {
+    Read,
+    KeyRequest { key_epoch: u64 },
+    Update { version: u64 },
+    /// An archived revision was restored as `version`
+    RevisionRestored { revision_id: u64, version: u64 },
+    Share {
+        user: Principal,
+        permission: SharePermission,
+        expires_at: Option<u64>,
+    },
+    Unshare {
+        user: Principal,
+        permission: SharePermission,
+    },
+    ShareExpired {
+        user: Principal,
+        permission: SharePermission,
+    },
+    GroupShare {
+        group_id: GroupId,
+        permission: SharePermission,
+    },
+    GroupUnshare {
+        group_id: GroupId,
+        permission: SharePermission,
+    },
+    InviteRedeemed {
+        invite_id: InviteId,
+        permission: SharePermission,
+    },
+    ShareAccepted {
+        permission: SharePermission,
+    },
+    ShareDeclined {
+        permission: SharePermission,
+    },
+}
+
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct AuditEntry {
+    /// Position in the note's log, increasing with every entry
+    pub seq: u64,
+    pub principal: Principal,
+    pub action: AuditAction,
+    pub timestamp: u64,
+}
+
+/// What happened to a note in the changefeed, see changes.rs
+#[derive(Debug, CandidType, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Trashed,
+    Restored,
+    Deleted,
+    /// Grants, group or workspace membership, or folder placement changed
+    AccessChanged,
+    OwnerChanged,
+}
+
+/// Changefeed entry as stored
+/// `revoked` lists the principals who could read the note before this
+/// change but not after it; they receive a tombstone
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct ChangeEntry {
+    pub seq: u64,
+    pub note_id: NoteId,
+    pub kind: ChangeKind,
+    pub timestamp: u64,
+    pub revoked: Vec<Principal>,
+}
+
+/// Changefeed entry as seen by one caller
+/// `note` holds the current state of the note, or `None` for a tombstone:
+/// the note was deleted or the caller lost access to it
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NoteChange {
+    pub seq: u64,
+    pub note_id: NoteId,
+    pub kind: ChangeKind,
+    pub timestamp: u64,
+    pub note: Option<Note>,
+}
+
+/// One batch of the changefeed
+/// `cursor` is the sequence to resume from, even when `items` is empty
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct ChangeFeed {
+    pub items: Vec<NoteChange>,
+    pub cursor: u64,
+    pub has_more: bool,
+}
+
+/// One page of an account export, see archive.rs
+/// `archive` is a candid-encoded `AccountArchive`
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct AccountExportPage {
+    pub archive: Vec<u8>,
+    pub next_cursor: Option<String>,
+}
+
+/// Self-contained, versioned slice of an account's data
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct AccountArchive {
+    pub version: u32,
+    pub exported_by: Principal,
+    pub exported_at: u64,
+    pub items: Vec<ArchiveItem>,
+}
+
+/// Exported note record; its revisions, chunks, attachments and operations follow it
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct ArchivedNote {
+    pub note: Note,
+    pub metadata: Option<NoteMetadata>,
+}
+
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub enum ArchiveItem {
+    Profile(UserProfile),
+    SearchIndex(SearchIndex),
+    Note(Box<ArchivedNote>),
+    Revision(NoteRevision),
+    Chunk {
+        note_id: NoteId,
+        index: u32,
+        data: String,
+    },
+    Attachment(Attachment),
+    Op(NoteOp),
+    /// Exported for the record; NFTs are not re-minted on import
+    Nft(Nft),
+}
+
+/// Account import in progress for its owner, see archive.rs
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct ImportSession {
+    pub owner: Principal,
+    pub started_at: u64,
+    pub notes_imported: u64,
+}
+
+/// Note ID in an imported archive and the ID of the note created for it
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq)]
+pub struct NoteIdMapping {
+    pub old_id: NoteId,
+    pub new_id: NoteId,
+}
+
+/// Exclusive, time-limited right to edit a note, see leases.rs
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct EditLease {
+    pub note_id: NoteId,
+    pub holder: Principal,
+    pub acquired_at: u64,
+    /// Pushed forward by every heartbeat
+    pub expires_at: u64,
+}
+
+/// What confirming an account deletion removed, see account.rs
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct AccountDeletionReceipt {
+    pub principal: Principal,
+    /// Username freed by the deletion
+    pub username: Option<String>,
+    pub deleted_at: u64,
+    pub deleted_notes: Vec<NoteId>,
+    /// Owned notes that were kept because they are minted as NFTs or belong to a workspace
+    pub retained_notes: Vec<NoteId>,
+    /// Notes of other users the account was removed from
+    pub revoked_shares: Vec<NoteId>,
+    /// Pending transfers offered to the account, declined on its behalf
+    pub declined_transfers: Vec<NoteId>,
+    pub deleted_groups: Vec<GroupId>,
+    pub left_groups: Vec<GroupId>,
+    pub left_workspaces: Vec<WorkspaceId>,
+    pub deleted_folders: Vec<FolderId>,
+    pub revoked_folder_shares: Vec<FolderId>,
+    pub search_index_deleted: bool,
+    pub profile_deleted: bool,
+}
+
+/// Identifies an operation in a note's op log, see oplog.rs
+/// Ordered by Lamport clock, with the author breaking ties between concurrent operations
+#[derive(Debug, CandidType, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OpId {
+    pub clock: u64,
+    pub author: Principal,
+}
+
+/// Client-encrypted CRDT operation on a note in op-log mode
+/// The payload is encrypted under the note key of `key_epoch`
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NoteOp {
+    pub note_id: NoteId,
+    /// Position in the log, in the order the canister received operations; never reused
+    pub seq: u128,
+    pub id: OpId,
+    /// Operations this one was causally based on
+    pub deps: Vec<OpId>,
+    pub encrypted: String,
+    pub key_epoch: u64,
+    pub created_at: u64,
+}
+
+/// Operation submitted by a client; the canister fills in the author and log position
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NewNoteOp {
+    pub clock: u64,
+    pub deps: Vec<OpId>,
+    pub encrypted: String,
+}
+
+/// Operations of a note after a log position, see `get_note_ops`
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct NoteOpBatch {
+    pub ops: Vec<NoteOp>,
+    /// Clock the note's current snapshot covers; a client behind it must reload the note first
+    pub snapshot_clock: u64,
+    /// Log position of the last operation in `ops`, the cursor for the next call
+    /// and the `seen_seq` to compact with
+    pub cursor: u128,
+    pub has_more: bool,
+}
+
+/// Cursor-based page request shared by all list endpoints
+/// `cursor` is the opaque `next_cursor` of the previous page; `None` starts from the beginning
+#[derive(Debug, CandidType, Deserialize, Clone, Default)]
+pub struct PageRequest {
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// One page of results; `next_cursor` is `None` on the last page
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Clone, Debug, candid::CandidType, serde::Deserialize, serde::Serialize)]
@@ -82,14 +797,141 @@ pub enum TransferError {
 }
 
 impl Note {
+    /// Trashed notes stay readable by their owner only
     pub fn can_read(&self, principal: &Principal) -> bool {
-        &self.owner == principal
-            || self.shared_read.contains(principal)
-            || self.shared_edit.contains(principal)
+        if self.is_trashed() {
+            return self.is_managed_by(principal);
+        }
+        self.is_managed_by(principal)
+            || self.workspace_role(principal).is_some()
+            || self.has_active_grant(principal, SharePermission::Read)
+            || self.has_active_grant(principal, SharePermission::Edit)
+            || self.has_active_grant(principal, SharePermission::Comment)
+            || is_member_of_any(&self.shared_read_groups, principal)
+            || is_member_of_any(&self.shared_edit_groups, principal)
+            || is_member_of_any(&self.shared_comment_groups, principal)
+            || self.folder_permission(principal).is_some()
     }
 
+    /// Trashed notes must be restored before they can be edited
     pub fn can_edit(&self, principal: &Principal) -> bool {
-        &self.owner == principal || self.shared_edit.contains(principal)
+        if self.is_trashed() {
+            return false;
+        }
+        self.is_managed_by(principal)
+            || self.workspace_role(principal).is_some()
+            || self.has_active_grant(principal, SharePermission::Edit)
+            || is_member_of_any(&self.shared_edit_groups, principal)
+            || self.folder_permission(principal) == Some(SharePermission::Edit)
+    }
+
+    /// Editors can always comment; read-only grantees cannot
+    pub fn can_comment(&self, principal: &Principal) -> bool {
+        if self.is_trashed() {
+            return false;
+        }
+        self.can_edit(principal)
+            || self.has_active_grant(principal, SharePermission::Comment)
+            || is_member_of_any(&self.shared_comment_groups, principal)
+            || self.folder_permission(principal) == Some(SharePermission::Comment)
+    }
+
+    /// Whether `principal` holds owner rights: sharing, deleting, reading the audit log
+    /// On workspace notes these belong to workspace admins and to the owner
+    /// for as long as they remain a member
+    pub fn is_managed_by(&self, principal: &Principal) -> bool {
+        match self.workspace_id {
+            Some(_) => match self.workspace_role(principal) {
+                Some(WorkspaceRole::Admin) => true,
+                Some(WorkspaceRole::Member) => &self.owner == principal,
+                None => false,
+            },
+            None => &self.owner == principal,
+        }
+    }
+
+    fn workspace_role(&self, principal: &Principal) -> Option<WorkspaceRole> {
+        self.workspace_id
+            .and_then(|workspace_id| workspace_role(workspace_id, principal))
+    }
+
+    /// Strongest permission `principal` holds through the note's folder or its ancestors
+    fn folder_permission(&self, principal: &Principal) -> Option<SharePermission> {
+        self.folder_id
+            .and_then(|folder_id| folder_permission(folder_id, principal))
+    }
+
+    pub fn grantees(&self, permission: SharePermission) -> &Vec<Principal> {
+        match permission {
+            SharePermission::Read => &self.shared_read,
+            SharePermission::Edit => &self.shared_edit,
+            SharePermission::Comment => &self.shared_comment,
+        }
+    }
+
+    pub fn grantees_mut(&mut self, permission: SharePermission) -> &mut Vec<Principal> {
+        match permission {
+            SharePermission::Read => &mut self.shared_read,
+            SharePermission::Edit => &mut self.shared_edit,
+            SharePermission::Comment => &mut self.shared_comment,
+        }
+    }
+
+    pub fn group_ids(&self, permission: SharePermission) -> &Vec<GroupId> {
+        match permission {
+            SharePermission::Read => &self.shared_read_groups,
+            SharePermission::Edit => &self.shared_edit_groups,
+            SharePermission::Comment => &self.shared_comment_groups,
+        }
+    }
+
+    pub fn group_ids_mut(&mut self, permission: SharePermission) -> &mut Vec<GroupId> {
+        match permission {
+            SharePermission::Read => &mut self.shared_read_groups,
+            SharePermission::Edit => &mut self.shared_edit_groups,
+            SharePermission::Comment => &mut self.shared_comment_groups,
+        }
+    }
+
+    pub fn share_expiry(&self, principal: &Principal, permission: SharePermission) -> Option<u64> {
+        self.share_expirations
+            .iter()
+            .find(|e| &e.user == principal && e.permission == permission)
+            .map(|e| e.expires_at)
+    }
+
+    /// Whether `principal` holds an unexpired grant at `permission` level
+    fn has_active_grant(&self, principal: &Principal, permission: SharePermission) -> bool {
+        self.grantees(permission).contains(principal)
+            && self
+                .share_expiry(principal, permission)
+                .is_none_or(|expires_at| expires_at > ic_cdk::api::time())
+    }
+
+    /// All grants on this note with their expiry
+    pub fn grants(&self) -> Vec<ShareGrant> {
+        [
+            SharePermission::Read,
+            SharePermission::Edit,
+            SharePermission::Comment,
+        ]
+        .into_iter()
+        .flat_map(|permission| {
+            self.grantees(permission).iter().map(move |user| ShareGrant {
+                user: *user,
+                permission,
+                expires_at: self.share_expiry(user, permission),
+            })
+        })
+        .collect()
+    }
+
+    pub fn is_trashed(&self) -> bool {
+        self.trashed_at.is_some()
+    }
+
+    pub fn is_chunked(&self) -> bool {
+        self.chunk_count > 0
     }
 }
 
@@ -105,8 +947,8 @@ impl Storable for Note {
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        match candid::Decode!(bytes.as_ref(), Self) {
-            Ok(note) => note,
+        match candid::Decode!(bytes.as_ref(), NoteRecord) {
+            Ok(record) => record.into(),
             Err(e) => {
                 debug_print(format!("Failed to decode Note: {}", e));
                 // Return a default Note; adjust as needed for your use case
@@ -116,6 +958,22 @@ impl Storable for Note {
                     encrypted: String::new(),
                     shared_read: Vec::new(),
                     shared_edit: Vec::new(),
+                    version: 0,
+                    created_at: 0,
+                    updated_at: 0,
+                    last_editor: Principal::anonymous(),
+                    trashed_at: None,
+                    chunk_count: 0,
+                    share_expirations: Vec::new(),
+                    key_epoch: 0,
+                    content_key_epoch: 0,
+                    shared_read_groups: Vec::new(),
+                    shared_edit_groups: Vec::new(),
+                    workspace_id: None,
+                    shared_comment: Vec::new(),
+                    shared_comment_groups: Vec::new(),
+                    folder_id: None,
+                    op_log_clock: None,
                 }
             }
         }
@@ -227,3 +1085,673 @@ impl Storable for Nft {
     const BOUND: ic_stable_structures::storable::Bound =
         ic_stable_structures::storable::Bound::Unbounded;
 }
+
+impl Storable for NoteRevision {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode NoteRevision: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(revision) => revision,
+            Err(e) => {
+                debug_print(format!("Failed to decode NoteRevision: {}", e));
+                NoteRevision {
+                    note_id: 0,
+                    revision_id: 0,
+                    encrypted: String::new(),
+                    author: Principal::anonymous(),
+                    created_at: 0,
+                    key_epoch: None,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for RevisionPolicy {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode RevisionPolicy: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(policy) => policy,
+            Err(e) => {
+                debug_print(format!("Failed to decode RevisionPolicy: {}", e));
+                RevisionPolicy {
+                    max_revisions: None,
+                    max_age_nanos: None,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for UploadSession {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode UploadSession: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(session) => session,
+            Err(e) => {
+                debug_print(format!("Failed to decode UploadSession: {}", e));
+                UploadSession {
+                    id: 0,
+                    owner: Principal::anonymous(),
+                    note_id: None,
+                    chunk_count: 0,
+                    total_size: 0,
+                    created_at: 0,
+                    updated_at: 0,
+                    key_epoch: None,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for NoteTransfer {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode NoteTransfer: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(transfer) => transfer,
+            Err(e) => {
+                debug_print(format!("Failed to decode NoteTransfer: {}", e));
+                NoteTransfer {
+                    note_id: 0,
+                    from: Principal::anonymous(),
+                    to: Principal::anonymous(),
+                    key_epoch: 0,
+                    offered_at: 0,
+                    staged_at: None,
+                    staged_version: None,
+                    accepted_at: None,
+                    cancelled_at: None,
+                    cancelled_by: None,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for AuditEntry {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode AuditEntry: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug_print(format!("Failed to decode AuditEntry: {}", e));
+                AuditEntry {
+                    seq: 0,
+                    principal: Principal::anonymous(),
+                    action: AuditAction::Read,
+                    timestamp: 0,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Group {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode Group: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(group) => group,
+            Err(e) => {
+                debug_print(format!("Failed to decode Group: {}", e));
+                Group {
+                    id: 0,
+                    owner: Principal::anonymous(),
+                    name: String::new(),
+                    members: Vec::new(),
+                    created_at: 0,
+                    updated_at: 0,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for ChangeEntry {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode ChangeEntry: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug_print(format!("Failed to decode ChangeEntry: {}", e));
+                ChangeEntry {
+                    seq: 0,
+                    note_id: 0,
+                    kind: ChangeKind::Updated,
+                    timestamp: 0,
+                    revoked: Vec::new(),
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for ImportSession {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode ImportSession: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(session) => session,
+            Err(e) => {
+                debug_print(format!("Failed to decode ImportSession: {}", e));
+                ImportSession {
+                    owner: Principal::anonymous(),
+                    started_at: 0,
+                    notes_imported: 0,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for EditLease {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode EditLease: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(lease) => lease,
+            Err(e) => {
+                debug_print(format!("Failed to decode EditLease: {}", e));
+                EditLease {
+                    note_id: 0,
+                    holder: Principal::anonymous(),
+                    acquired_at: 0,
+                    expires_at: 0,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for NoteOp {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode NoteOp: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(op) => op,
+            Err(e) => {
+                debug_print(format!("Failed to decode NoteOp: {}", e));
+                NoteOp {
+                    note_id: 0,
+                    seq: 0,
+                    id: OpId {
+                        clock: 0,
+                        author: Principal::anonymous(),
+                    },
+                    deps: Vec::new(),
+                    encrypted: String::new(),
+                    key_epoch: 0,
+                    created_at: 0,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for NoteMetadata {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode NoteMetadata: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                debug_print(format!("Failed to decode NoteMetadata: {}", e));
+                NoteMetadata {
+                    encrypted: String::new(),
+                    key_epoch: 0,
+                    updated_at: 0,
+                    updated_by: Principal::anonymous(),
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Folder {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode Folder: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(folder) => folder,
+            Err(e) => {
+                debug_print(format!("Failed to decode Folder: {}", e));
+                Folder {
+                    id: 0,
+                    owner: Principal::anonymous(),
+                    parent_id: None,
+                    encrypted_name: String::new(),
+                    shared_read: Vec::new(),
+                    shared_edit: Vec::new(),
+                    shared_comment: Vec::new(),
+                    key_epoch: 0,
+                    name_key_epoch: 0,
+                    created_at: 0,
+                    updated_at: 0,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Workspace {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode Workspace: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(workspace) => workspace,
+            Err(e) => {
+                debug_print(format!("Failed to decode Workspace: {}", e));
+                Workspace {
+                    id: 0,
+                    name: String::new(),
+                    members: Vec::new(),
+                    created_at: 0,
+                    updated_at: 0,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for WorkspaceRole {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode WorkspaceRole: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(role) => role,
+            Err(e) => {
+                debug_print(format!("Failed to decode WorkspaceRole: {}", e));
+                WorkspaceRole::Member
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Comment {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode Comment: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(comment) => comment,
+            Err(e) => {
+                debug_print(format!("Failed to decode Comment: {}", e));
+                Comment {
+                    id: 0,
+                    note_id: 0,
+                    parent_id: None,
+                    author: Principal::anonymous(),
+                    encrypted_body: String::new(),
+                    key_epoch: 0,
+                    resolved: false,
+                    resolved_by: None,
+                    deleted: false,
+                    created_at: 0,
+                    updated_at: 0,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for NoteInvite {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode NoteInvite: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(invite) => invite,
+            Err(e) => {
+                debug_print(format!("Failed to decode NoteInvite: {}", e));
+                NoteInvite {
+                    id: 0,
+                    note_id: 0,
+                    created_by: Principal::anonymous(),
+                    permission: SharePermission::Read,
+                    token_hash: Vec::new(),
+                    created_at: 0,
+                    expires_at: 0,
+                    redeemed_by: None,
+                    redeemed_at: None,
+                    revoked_at: None,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for PendingShare {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode PendingShare: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(share) => share,
+            Err(e) => {
+                debug_print(format!("Failed to decode PendingShare: {}", e));
+                PendingShare {
+                    note_id: 0,
+                    from: Principal::anonymous(),
+                    permission: SharePermission::Read,
+                    expires_at: None,
+                    created_at: 0,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Attachment {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        match Encode!(self) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(e) => {
+                debug_print(format!("Failed to encode Attachment: {}", e));
+                Cow::Owned(Vec::new())
+            }
+        }
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match Decode!(bytes.as_ref(), Self) {
+            Ok(attachment) => attachment,
+            Err(e) => {
+                debug_print(format!("Failed to decode Attachment: {}", e));
+                Attachment {
+                    id: 0,
+                    note_id: 0,
+                    mime_type: String::new(),
+                    size: 0,
+                    data: Vec::new(),
+                    uploaded_by: Principal::anonymous(),
+                    created_at: 0,
+                    key_epoch: None,
+                }
+            }
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(CandidType)]
+    struct LegacyNote {
+        id: NoteId,
+        owner: Principal,
+        encrypted: String,
+        shared_read: Vec<Principal>,
+    }
+
+    #[test]
+    fn test_legacy_note_decodes_with_defaults() {
+        let legacy = LegacyNote {
+            id: 7,
+            owner: Principal::management_canister(),
+            encrypted: "ciphertext".to_string(),
+            shared_read: vec![Principal::anonymous()],
+        };
+        let bytes = Encode!(&legacy).unwrap();
+
+        let note = Note::from_bytes(Cow::Owned(bytes));
+        assert_eq!(note.id, 7);
+        assert_eq!(note.owner, Principal::management_canister());
+        assert_eq!(note.encrypted, "ciphertext");
+        assert_eq!(note.shared_read, vec![Principal::anonymous()]);
+        assert!(note.shared_edit.is_empty());
+        assert_eq!(note.version, 0);
+        assert_eq!(note.created_at, 0);
+        assert_eq!(note.updated_at, 0);
+        assert_eq!(note.last_editor, Principal::management_canister());
+        assert_eq!(note.trashed_at, None);
+        assert_eq!(note.chunk_count, 0);
+        assert!(note.share_expirations.is_empty());
+        assert_eq!(note.key_epoch, 0);
+        assert_eq!(note.content_key_epoch, 0);
+        assert!(note.shared_read_groups.is_empty());
+        assert_eq!(note.workspace_id, None);
+        assert_eq!(note.folder_id, None);
+        assert_eq!(note.op_log_clock, None);
+    }
+
+    #[test]
+    fn test_note_roundtrip() {
+        let note = Note {
+            id: 3,
+            owner: Principal::management_canister(),
+            encrypted: "ciphertext".to_string(),
+            shared_read: vec![],
+            shared_edit: vec![Principal::anonymous()],
+            version: 5,
+            created_at: 10,
+            updated_at: 20,
+            last_editor: Principal::anonymous(),
+            trashed_at: Some(30),
+            chunk_count: 2,
+            share_expirations: vec![ShareExpiration {
+                user: Principal::anonymous(),
+                permission: SharePermission::Edit,
+                expires_at: 40,
+            }],
+            key_epoch: 3,
+            content_key_epoch: 2,
+            shared_read_groups: vec![7],
+            shared_edit_groups: vec![],
+            workspace_id: Some(5),
+            shared_comment: vec![],
+            shared_comment_groups: vec![],
+            folder_id: Some(9),
+            op_log_clock: Some(4),
+        };
+
+        let decoded = Note::from_bytes(note.to_bytes());
+        assert_eq!(decoded.shared_edit, vec![Principal::anonymous()]);
+        assert_eq!(decoded.version, 5);
+        assert_eq!(decoded.created_at, 10);
+        assert_eq!(decoded.updated_at, 20);
+        assert_eq!(decoded.last_editor, Principal::anonymous());
+        assert_eq!(decoded.trashed_at, Some(30));
+        assert_eq!(decoded.chunk_count, 2);
+        assert_eq!(decoded.share_expirations, note.share_expirations);
+        assert_eq!(decoded.key_epoch, 3);
+        assert_eq!(decoded.content_key_epoch, 2);
+        assert_eq!(decoded.shared_read_groups, vec![7]);
+        assert_eq!(decoded.workspace_id, Some(5));
+        assert_eq!(decoded.folder_id, Some(9));
+        assert_eq!(decoded.op_log_clock, Some(4));
+    }
+}
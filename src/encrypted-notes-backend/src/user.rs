@@ -5,9 +5,11 @@ use candid::Principal;
 use ic_cdk::api::msg_caller;
 use ic_cdk::{query, update};
 
-use crate::helpers::assert_not_anonymous;
+use std::ops::Bound;
+
+use crate::helpers::{assert_not_anonymous, collect_pages, paginate, parse_cursor};
 use crate::storage::USER_PROFILES;
-use crate::types::UserProfile;
+use crate::types::{Page, PageRequest, UserProfile};
 
 /// Check if a username is already taken by another user
 /// Returns true if the username is available, false if taken
@@ -26,6 +28,18 @@ pub fn is_username_available(username: String) -> bool {
     })
 }
 
+/// Find the registered user holding `username`, matched case-insensitively
+pub fn find_user_by_username(username: &str) -> Option<Principal> {
+    let username_lower = username.trim().to_lowercase();
+
+    USER_PROFILES.with(|map| {
+        map.borrow()
+            .iter()
+            .find(|(_, profile)| profile.username.to_lowercase() == username_lower)
+            .map(|(id, _)| id)
+    })
+}
+
 /// Check if a username is available for a specific user (for updates)
 /// Returns true if the username is available or belongs to the caller
 #[query]
@@ -79,11 +93,28 @@ pub fn get_profile(principal: Principal) -> Option<UserProfile> {
     USER_PROFILES.with(|map| map.borrow().get(&principal))
 }
 
+/// Remove the profile of `principal`, freeing its username
+pub fn delete_profile(principal: Principal) -> Option<UserProfile> {
+    USER_PROFILES.with(|map| map.borrow_mut().remove(&principal))
+}
+
 /// Get all registered users
 /// Returns a list of all user profiles in the system
+/// Unpaged wrapper around `get_registered_users_page`, kept until clients migrate
 #[query]
 pub fn get_registered_users() -> Vec<UserProfile> {
-    USER_PROFILES.with(|map| map.borrow().iter().map(|(_, v)| v).collect())
+    collect_pages(get_registered_users_page)
+}
+
+/// Get one page of registered users, ordered by principal
+#[query]
+pub fn get_registered_users_page(request: PageRequest) -> Page<UserProfile> {
+    let start = match parse_cursor::<Principal>(&request) {
+        Some(cursor) => Bound::Excluded(cursor),
+        None => Bound::Unbounded,
+    };
+
+    USER_PROFILES.with(|map| paginate(map.borrow().range((start, Bound::Unbounded)), &request))
 }
 
 /// Get all users except the specified caller
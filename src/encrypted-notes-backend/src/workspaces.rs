@@ -0,0 +1,412 @@
+// Team Workspaces Module
+// src/encrypted-notes-backend/src/workspaces.rs
+//
+// A workspace owns notes on behalf of a team. Every member can read and edit
+// workspace notes, while owner rights (sharing, deleting, reading the audit
+// log) belong to admins and to the member currently stewarding the note as
+// its `owner`. Workspace notes derive their vetKD key from the workspace
+// rather than the owner, so admins can reassign them when someone leaves
+// without re-encrypting anything. Removing a member rotates the key epoch of
+// every workspace note.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use ic_cdk::{query, update};
+
+use crate::changes::{record_access_change, record_note_change};
+use crate::helpers::{assert_not_anonymous, get_max_note_size, get_next_id};
+use crate::index::{index_note, unindex_note, workspace_note_ids};
+use crate::note::{insert_note, notes_page};
+use crate::storage::{NOTES, WORKSPACES, WORKSPACE_MEMBERS};
+use crate::trash::{mark_trashed, unmark_trashed};
+use crate::types::{
+    ChangeKind, Note, NoteId, Page, PageRequest, Workspace, WorkspaceId, WorkspaceMember,
+    WorkspaceRole,
+};
+
+const MAX_WORKSPACE_NAME_LENGTH: usize = 100;
+const MAX_WORKSPACE_MEMBERS: usize = 500;
+
+/// Role of `principal` in a workspace, if they are a member
+pub fn workspace_role(workspace_id: WorkspaceId, principal: &Principal) -> Option<WorkspaceRole> {
+    WORKSPACE_MEMBERS.with_borrow(|members| members.get(&(*principal, workspace_id)))
+}
+
+/// Create a workspace with the caller as its first admin
+/// Returns the new workspace ID
+#[update]
+pub fn create_workspace(name: String) -> WorkspaceId {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+    let name = validate_workspace_name(&name);
+
+    let workspace_id = get_next_id();
+    let now = ic_cdk::api::time();
+    let workspace = Workspace {
+        id: workspace_id,
+        name,
+        members: vec![WorkspaceMember {
+            principal: caller,
+            role: WorkspaceRole::Admin,
+        }],
+        created_at: now,
+        updated_at: now,
+    };
+
+    WORKSPACE_MEMBERS
+        .with_borrow_mut(|members| members.insert((caller, workspace_id), WorkspaceRole::Admin));
+    WORKSPACES.with_borrow_mut(|workspaces| workspaces.insert(workspace_id, workspace));
+
+    workspace_id
+}
+
+/// Rename a workspace
+/// Only admins can rename it
+#[update]
+pub fn rename_workspace(workspace_id: WorkspaceId, name: String) {
+    let caller = msg_caller();
+    let mut workspace = administered_workspace(workspace_id, &caller);
+
+    workspace.name = validate_workspace_name(&name);
+    workspace.updated_at = ic_cdk::api::time();
+    WORKSPACES.with_borrow_mut(|workspaces| workspaces.insert(workspace_id, workspace));
+}
+
+/// Add a member to a workspace or change their role
+/// Only admins can manage membership; the last admin cannot be demoted
+#[update]
+pub fn set_workspace_member(workspace_id: WorkspaceId, user: Principal, role: WorkspaceRole) {
+    let caller = msg_caller();
+    let mut workspace = administered_workspace(workspace_id, &caller);
+
+    if user == Principal::anonymous() {
+        ic_cdk::trap("Anonymous principal cannot join a workspace");
+    }
+
+    match workspace.members.iter_mut().find(|m| m.principal == user) {
+        Some(member) => member.role = role,
+        None => {
+            if workspace.members.len() >= MAX_WORKSPACE_MEMBERS {
+                ic_cdk::trap(format!(
+                    "Workspaces can have at most {} members",
+                    MAX_WORKSPACE_MEMBERS
+                ));
+            }
+            workspace.members.push(WorkspaceMember {
+                principal: user,
+                role,
+            });
+        }
+    }
+    assert_has_admin(&workspace);
+
+    let joined = WORKSPACE_MEMBERS
+        .with_borrow_mut(|members| members.insert((user, workspace_id), role))
+        .is_none();
+    workspace.updated_at = ic_cdk::api::time();
+    WORKSPACES.with_borrow_mut(|workspaces| workspaces.insert(workspace_id, workspace));
+
+    if joined {
+        NOTES.with_borrow(|store| {
+            for note_id in workspace_note_ids(workspace_id) {
+                if let Some(note) = store.get(&note_id) {
+                    record_access_change(&note, &[]);
+                }
+            }
+        });
+    }
+}
+
+/// Remove a member from a workspace, revoking their access to its notes
+/// Admins can remove anyone and members can remove themselves; the last
+/// admin cannot leave. Notes the member stewards are handed to an admin and
+/// every workspace note moves to a new key epoch.
+#[update]
+pub fn remove_workspace_member(workspace_id: WorkspaceId, user: Principal) {
+    let caller = msg_caller();
+    let mut workspace = WORKSPACES
+        .with_borrow(|workspaces| workspaces.get(&workspace_id))
+        .unwrap_or_else(|| ic_cdk::trap("Workspace not found"));
+
+    if caller != user && workspace_role(workspace_id, &caller) != Some(WorkspaceRole::Admin) {
+        ic_cdk::trap("Only workspace admins can remove members");
+    }
+
+    let before = workspace.members.len();
+    workspace.members.retain(|m| m.principal != user);
+    if workspace.members.len() == before {
+        ic_cdk::trap("Principal is not a member of this workspace");
+    }
+    assert_has_admin(&workspace);
+
+    WORKSPACE_MEMBERS.with_borrow_mut(|members| members.remove(&(user, workspace_id)));
+    revoke_member(&workspace, user);
+    workspace.updated_at = ic_cdk::api::time();
+    WORKSPACES.with_borrow_mut(|workspaces| workspaces.insert(workspace_id, workspace));
+}
+
+/// Workspaces where `user` is the only admin but not the only member
+pub fn sole_admin_workspaces(user: Principal) -> Vec<WorkspaceId> {
+    load_workspaces(member_workspace_ids(user))
+        .into_iter()
+        .filter(|workspace| {
+            workspace.members.len() > 1
+                && workspace
+                    .members
+                    .iter()
+                    .all(|m| m.role != WorkspaceRole::Admin || m.principal == user)
+        })
+        .map(|workspace| workspace.id)
+        .collect()
+}
+
+/// Remove `user` from every workspace they belong to
+/// Notes they steward are handed to an admin; a workspace they are the only
+/// member of is dissolved and its notes are left to the caller to delete.
+/// Returns the workspaces left and the notes handed over.
+pub fn leave_workspaces(user: Principal) -> (Vec<WorkspaceId>, Vec<NoteId>) {
+    let mut left = Vec::new();
+    let mut handed_over = Vec::new();
+
+    for mut workspace in load_workspaces(member_workspace_ids(user)) {
+        let workspace_id = workspace.id;
+        workspace.members.retain(|m| m.principal != user);
+        WORKSPACE_MEMBERS.with_borrow_mut(|members| members.remove(&(user, workspace_id)));
+        left.push(workspace_id);
+
+        if workspace.members.is_empty() {
+            WORKSPACES.with_borrow_mut(|workspaces| workspaces.remove(&workspace_id));
+            continue;
+        }
+        handed_over.extend(revoke_member(&workspace, user));
+
+        workspace.updated_at = ic_cdk::api::time();
+        WORKSPACES.with_borrow_mut(|workspaces| workspaces.insert(workspace_id, workspace));
+    }
+    (left, handed_over)
+}
+
+/// Get a workspace by ID
+/// Visible to its members
+#[query]
+pub fn get_workspace(workspace_id: WorkspaceId) -> Option<Workspace> {
+    let caller = msg_caller();
+
+    workspace_role(workspace_id, &caller)?;
+    WORKSPACES.with_borrow(|workspaces| workspaces.get(&workspace_id))
+}
+
+/// Get the workspaces the caller belongs to
+#[query]
+pub fn get_my_workspaces() -> Vec<Workspace> {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    load_workspaces(member_workspace_ids(caller))
+}
+
+/// Create a note owned by a workspace, stewarded by the caller
+/// Requires workspace membership; returns the new note ID
+#[update]
+pub fn create_workspace_note(workspace_id: WorkspaceId, encrypted: String) -> NoteId {
+    let caller = msg_caller();
+    let _ = assert_not_anonymous(&caller);
+
+    if workspace_role(workspace_id, &caller).is_none() {
+        ic_cdk::trap("Only workspace members can create workspace notes");
+    }
+    let max_size = get_max_note_size();
+    if encrypted.len() > max_size {
+        ic_cdk::trap(format!(
+            "Note too large: {} bytes exceeds limit of {} bytes",
+            encrypted.len(),
+            max_size
+        ));
+    }
+
+    insert_note(caller, encrypted, 0, Some(workspace_id))
+}
+
+/// List one page of a workspace's notes, ordered by note ID
+/// Requires workspace membership
+#[query]
+pub fn get_workspace_notes(workspace_id: WorkspaceId, request: PageRequest) -> Page<Note> {
+    let caller = msg_caller();
+
+    if workspace_role(workspace_id, &caller).is_none() {
+        ic_cdk::trap("Only workspace members can list workspace notes");
+    }
+
+    notes_page(workspace_note_ids(workspace_id), &request, |note| {
+        note.can_read(&caller)
+    })
+}
+
+/// Hand a workspace note to another member, e.g. when its owner leaves
+/// Only admins can reassign; the note key does not change
+#[update]
+pub fn reassign_workspace_note(note_id: NoteId, new_owner: Principal) {
+    let caller = msg_caller();
+
+    NOTES.with_borrow_mut(|store| {
+        let mut note = store
+            .get(&note_id)
+            .unwrap_or_else(|| ic_cdk::trap("Note not found"));
+        let workspace_id = note
+            .workspace_id
+            .unwrap_or_else(|| ic_cdk::trap("Note does not belong to a workspace"));
+
+        if workspace_role(workspace_id, &caller) != Some(WorkspaceRole::Admin) {
+            ic_cdk::trap("Only workspace admins can reassign notes");
+        }
+        if workspace_role(workspace_id, &new_owner).is_none() {
+            ic_cdk::trap("New owner must be a member of the workspace");
+        }
+
+        if note.is_trashed() {
+            ic_cdk::trap("Restore the note before reassigning it");
+        }
+
+        unindex_note(&note);
+        note.owner = new_owner;
+        index_note(&note);
+        record_note_change(None, &note, ChangeKind::OwnerChanged);
+        store.insert(note_id, note);
+    });
+}
+
+fn validate_workspace_name(name: &str) -> String {
+    let name = name.trim();
+    if name.is_empty() || name.len() > MAX_WORKSPACE_NAME_LENGTH {
+        ic_cdk::trap(format!(
+            "Workspace name must be between 1 and {} characters",
+            MAX_WORKSPACE_NAME_LENGTH
+        ));
+    }
+    name.to_string()
+}
+
+fn administered_workspace(workspace_id: WorkspaceId, caller: &Principal) -> Workspace {
+    let workspace = WORKSPACES
+        .with_borrow(|workspaces| workspaces.get(&workspace_id))
+        .unwrap_or_else(|| ic_cdk::trap("Workspace not found"));
+    if workspace_role(workspace_id, caller) != Some(WorkspaceRole::Admin) {
+        ic_cdk::trap("Only workspace admins can manage this workspace");
+    }
+    workspace
+}
+
+/// Cut a removed member off the notes of `workspace`, which no longer lists them
+/// Notes they steward go to the first admin, and every note moves to a new
+/// key epoch, trashed ones included. Returns the notes handed over.
+fn revoke_member(workspace: &Workspace, user: Principal) -> Vec<NoteId> {
+    let steward = workspace
+        .members
+        .iter()
+        .find(|m| m.role == WorkspaceRole::Admin)
+        .map(|m| m.principal)
+        .unwrap_or_else(|| ic_cdk::trap("A workspace must keep at least one admin"));
+
+    let mut handed_over = Vec::new();
+    NOTES.with_borrow_mut(|store| {
+        for note_id in workspace_note_ids(workspace.id) {
+            let Some(mut note) = store.get(&note_id) else {
+                continue;
+            };
+            if note.owner == user {
+                match note.trashed_at {
+                    Some(trashed_at) => {
+                        unmark_trashed(user, note_id);
+                        mark_trashed(steward, note_id, trashed_at);
+                    }
+                    None => unindex_note(&note),
+                }
+                note.owner = steward;
+                if !note.is_trashed() {
+                    index_note(&note);
+                }
+                record_note_change(None, &note, ChangeKind::OwnerChanged);
+                handed_over.push(note_id);
+            }
+            note.key_epoch += 1;
+            record_access_change(&note, &[user]);
+            store.insert(note_id, note);
+        }
+    });
+    handed_over
+}
+
+fn member_workspace_ids(user: Principal) -> Vec<WorkspaceId> {
+    WORKSPACE_MEMBERS.with_borrow(|members| {
+        members
+            .keys_range((user, 0)..=(user, WorkspaceId::MAX))
+            .map(|(_, workspace_id)| workspace_id)
+            .collect()
+    })
+}
+
+fn load_workspaces(workspace_ids: Vec<WorkspaceId>) -> Vec<Workspace> {
+    WORKSPACES.with_borrow(|workspaces| {
+        workspace_ids
+            .into_iter()
+            .filter_map(|workspace_id| workspaces.get(&workspace_id))
+            .collect()
+    })
+}
+
+fn assert_has_admin(workspace: &Workspace) {
+    if !workspace
+        .members
+        .iter()
+        .any(|m| m.role == WorkspaceRole::Admin)
+    {
+        ic_cdk::trap("A workspace must keep at least one admin");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::TRASHED_NOTES;
+    use crate::test_utils::{note, principal};
+    use crate::trash::move_to_trash;
+
+    #[test]
+    fn test_revoking_a_member_rotates_trashed_workspace_notes() {
+        let (alice, bob, carol) = (principal(1), principal(2), principal(3));
+        let workspace = Workspace {
+            id: 1,
+            name: "team".to_string(),
+            members: vec![
+                WorkspaceMember {
+                    principal: alice,
+                    role: WorkspaceRole::Admin,
+                },
+                WorkspaceMember {
+                    principal: carol,
+                    role: WorkspaceRole::Member,
+                },
+            ],
+            created_at: 0,
+            updated_at: 0,
+        };
+        for (note_id, owner) in [(1, carol), (2, bob)] {
+            let mut trashed = Note {
+                workspace_id: Some(1),
+                ..note(note_id, owner)
+            };
+            index_note(&trashed);
+            move_to_trash(&mut trashed);
+            NOTES.with_borrow_mut(|store| store.insert(note_id, trashed));
+        }
+
+        assert_eq!(revoke_member(&workspace, bob), vec![2]);
+
+        let (kept, handed_over) =
+            NOTES.with_borrow(|store| (store.get(&1).unwrap(), store.get(&2).unwrap()));
+        assert_eq!(kept.key_epoch, 1);
+        assert_eq!(handed_over.key_epoch, 1);
+        assert_eq!(handed_over.owner, alice);
+        assert!(TRASHED_NOTES.with_borrow(|trash| trash.contains_key(&(alice, 2))));
+    }
+}